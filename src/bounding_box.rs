@@ -1,61 +1,94 @@
-use cgmath::{Angle, Vector3};
+use crate::part::Part;
+use crate::view::{to_render_space, View};
+use cgmath::{Angle, EuclideanSpace, InnerSpace, Vector3};
 
-pub enum Axis {
-    X,
-    Y,
-    Z,
-}
+/// How much bigger than the part an orthographic camera's view is, so that the
+/// part doesn't touch the edges of the image.
+const ORTHOGRAPHIC_MARGIN: f32 = 1.1;
+
+/// The closest the near clipping plane can be to the camera, in millimetres.
+const MIN_ZNEAR: f32 = 1e-6;
 
+/// The bounds of a part, in double precision so that parts far from the
+/// origin can be centered without losing detail.
 pub struct BoundingBox {
-    pub x_min: f32,
-    pub x_max: f32,
-    pub y_min: f32,
-    pub y_max: f32,
-    pub z_min: f32,
-    pub z_max: f32,
+    pub x_min: f64,
+    pub x_max: f64,
+    pub y_min: f64,
+    pub y_max: f64,
+    pub z_min: f64,
+    pub z_max: f64,
+    /// Vertices with a NaN or infinite coordinate, which are left out of the
+    /// bounds.
+    pub skipped_vertices: usize,
 }
 
 impl BoundingBox {
-    pub fn new(mesh: &nom_stl::Mesh) -> BoundingBox {
+    /// Returns the bounds of the part's vertices as the renderer will see
+    /// them. Vertices with a NaN or infinite coordinate are left out and
+    /// counted in `skipped_vertices`. A part without any other vertices gets
+    /// an empty box at its origin.
+    pub fn new(part: &Part) -> BoundingBox {
+        let mut bounding_box = Self::from_points(
+            part.positions
+                .iter()
+                .map(|&position| to_render_space(position)),
+        );
+        bounding_box.shift(to_render_space(part.origin));
+        bounding_box
+    }
+
+    /// Returns the bounds of the part's vertices in its own coordinates, like
+    /// `new`.
+    pub fn in_part_space(part: &Part) -> BoundingBox {
+        let mut bounding_box = Self::from_points(
+            part.positions
+                .iter()
+                .map(|&position| Vector3::from(position)),
+        );
+        bounding_box.shift(Vector3::from(part.origin));
+        bounding_box
+    }
+
+    fn from_points(points: impl Iterator<Item = Vector3<f32>>) -> BoundingBox {
         let mut bounding_box = BoundingBox {
-            x_min: 0.0,
-            x_max: 0.0,
-            y_min: 0.0,
-            y_max: 0.0,
-            z_min: 0.0,
-            z_max: 0.0,
+            x_min: f64::INFINITY,
+            x_max: f64::NEG_INFINITY,
+            y_min: f64::INFINITY,
+            y_max: f64::NEG_INFINITY,
+            z_min: f64::INFINITY,
+            z_max: f64::NEG_INFINITY,
+            skipped_vertices: 0,
         };
 
-        for triangle in mesh.triangles() {
-            for vertex in triangle.vertices().iter() {
-                // Shif the coordinates around since the renderer will rotate
-                // the model -90 degrees around the x axis.
-                let (x, y, z) = (vertex[0], vertex[2], -1.0 * vertex[1]);
-                if x < bounding_box.x_min {
-                    bounding_box.x_min = x;
-                }
-                if x > bounding_box.x_max {
-                    bounding_box.x_max = x;
-                }
-                if y < bounding_box.y_min {
-                    bounding_box.y_min = y;
-                }
-                if y > bounding_box.y_max {
-                    bounding_box.y_max = y;
-                }
-                if z < bounding_box.z_min {
-                    bounding_box.z_min = z;
-                }
-                if z > bounding_box.z_max {
-                    bounding_box.z_max = z;
-                }
+        for vertex in points {
+            if !(vertex.x.is_finite() && vertex.y.is_finite() && vertex.z.is_finite()) {
+                bounding_box.skipped_vertices += 1;
+                continue;
             }
+
+            let (x, y, z) = (vertex.x as f64, vertex.y as f64, vertex.z as f64);
+            bounding_box.x_min = bounding_box.x_min.min(x);
+            bounding_box.x_max = bounding_box.x_max.max(x);
+            bounding_box.y_min = bounding_box.y_min.min(y);
+            bounding_box.y_max = bounding_box.y_max.max(y);
+            bounding_box.z_min = bounding_box.z_min.min(z);
+            bounding_box.z_max = bounding_box.z_max.max(z);
+        }
+
+        if bounding_box.x_min > bounding_box.x_max {
+            bounding_box.x_min = 0.0;
+            bounding_box.x_max = 0.0;
+            bounding_box.y_min = 0.0;
+            bounding_box.y_max = 0.0;
+            bounding_box.z_min = 0.0;
+            bounding_box.z_max = 0.0;
         }
 
         bounding_box
     }
 
-    pub fn shift(&mut self, vec: Vector3<f32>) {
+    pub fn shift(&mut self, vec: Vector3<f64>) {
         self.x_min += vec.x;
         self.x_max += vec.x;
         self.y_min += vec.y;
@@ -64,70 +97,61 @@ impl BoundingBox {
         self.z_max += vec.z;
     }
 
-    pub fn dx(&self) -> f32 {
+    pub fn dx(&self) -> f64 {
         self.x_max - self.x_min
     }
 
-    pub fn dy(&self) -> f32 {
+    pub fn dy(&self) -> f64 {
         self.y_max - self.y_min
     }
 
-    pub fn dz(&self) -> f32 {
+    pub fn dz(&self) -> f64 {
         self.z_max - self.z_min
     }
 
-    pub fn center(&self) -> cgmath::Point3<f32> {
+    pub fn center(&self) -> cgmath::Point3<f64> {
         let x = self.x_min + self.dx() / 2.0;
         let y = self.y_min + self.dy() / 2.0;
         let z = self.z_min + self.dz() / 2.0;
         cgmath::Point3::new(x, y, z)
     }
 
-    pub fn center_to_origin(&self) -> cgmath::Vector3<f32> {
+    pub fn center_to_origin(&self) -> cgmath::Vector3<f64> {
         let origin = cgmath::Point3::new(0.0, 0.0, 0.0);
         origin - self.center()
     }
 
-    /// Returns the axis along which the bounding box has the larget cross
-    /// section area.
-    pub fn largest_cross_section_axis(&self) -> Axis {
-        let x_axis_area = self.dy() * self.dz();
-        let y_axis_area = self.dx() * self.dz();
-        let z_axis_area = self.dx() * self.dy();
+    /// Returns the length of the bounding box's shadow on a line along the
+    /// given unit vector.
+    pub fn extent_along(&self, direction: Vector3<f32>) -> f32 {
+        (self.dx() * direction.x.abs() as f64
+            + self.dy() * direction.y.abs() as f64
+            + self.dz() * direction.z.abs() as f64) as f32
+    }
 
-        if x_axis_area > z_axis_area {
-            Axis::X
-        } else {
-            Axis::Z
-        }
-        // if y_axis_area > x_axis_area && y_axis_area > z_axis_area {
-        //     Axis::Y
-        // } else if x_axis_area > z_axis_area {
-        //     Axis::X
-        // } else {
-        //     Axis::Z
-        // }
-    }
-
-    /// Returns the width and height of the bounding box cross section when
-    /// viewed along the given axis.
-    pub fn visible_size(&self, axis: &Axis) -> (f32, f32) {
-        match axis {
-            Axis::X => (self.dz(), self.dy()),
-            Axis::Y => (self.dx(), self.dz()),
-            Axis::Z => (self.dx(), self.dy()),
-        }
+    /// Returns the area of the bounding box's silhouette when viewed along the
+    /// given unit vector.
+    pub fn projected_area(&self, direction: Vector3<f32>) -> f32 {
+        (self.dy() * self.dz() * direction.x.abs() as f64
+            + self.dx() * self.dz() * direction.y.abs() as f64
+            + self.dx() * self.dy() * direction.z.abs() as f64) as f32
     }
 
-    /// Returns a position on the given axis where the camera will be filled
-    /// with the part at the origin.
+    /// Returns the width and height of the bounding box's silhouette from the
+    /// given view.
+    pub fn visible_size(&self, view: &View) -> (f32, f32) {
+        (self.extent_along(view.right()), self.extent_along(view.up))
+    }
+
+    /// Returns a position in the given view's direction where the camera will
+    /// be filled with the part at the origin.
     pub fn pick_camera_position(
         &self,
         aspect: f32,
         fovy: cgmath::Deg<f32>,
-        axis: &Axis,
+        view: &View,
     ) -> cgmath::Point3<f32> {
-        let (visible_width, visible_height) = self.visible_size(axis);
+        let (visible_width, visible_height) = self.visible_size(view);
         let theta = cgmath::Deg(90.0) - fovy / 2.0;
         let target_height = if aspect >= visible_width / visible_height {
             visible_height
@@ -135,18 +159,198 @@ impl BoundingBox {
             1.0 / aspect * visible_width
         };
         let camera_to_box = theta.sin() * target_height;
-        match axis {
-            Axis::X => cgmath::Point3::new(camera_to_box + self.dx(), 0.0, 0.0),
-            Axis::Y => cgmath::Point3::new(0.0, camera_to_box + self.dy(), 0.0),
-            Axis::Z => cgmath::Point3::new(0.0, 0.0, camera_to_box + self.dz()),
+        let distance = camera_to_box + self.extent_along(view.direction);
+        cgmath::Point3::from_vec(view.direction * distance)
+    }
+
+    /// Returns the width and height of an orthographic camera's view, looking
+    /// at the target from the given view, that fit the bounding box with the
+    /// given aspect ratio. The bounding box must be centered on the origin.
+    pub fn pick_orthographic_size(
+        &self,
+        aspect: f32,
+        view: &View,
+        target: cgmath::Point3<f32>,
+    ) -> (f32, f32) {
+        let (visible_width, visible_height) = self.visible_size(view);
+        // A target off to one side has to see as far past it on the other.
+        let visible_width = visible_width + 2.0 * target.to_vec().dot(view.right()).abs();
+        let visible_height = visible_height + 2.0 * target.to_vec().dot(view.up).abs();
+        let (width, height) = if aspect >= visible_width / visible_height {
+            (aspect * visible_height, visible_height)
+        } else {
+            (visible_width, visible_width / aspect)
+        };
+        (width * ORTHOGRAPHIC_MARGIN, height * ORTHOGRAPHIC_MARGIN)
+    }
+
+    /// Returns the distances from the camera to the near and far clipping
+    /// planes that closely enclose the bounding box, so that depth precision
+    /// isn't wasted however big or small the part is. The bounding box must
+    /// be centered on the origin.
+    pub fn pick_clipping_planes(&self, camera_position: cgmath::Point3<f32>) -> (f32, f32) {
+        let distance = camera_position.to_vec().magnitude();
+        let radius =
+            ((self.dx().powi(2) + self.dy().powi(2) + self.dz().powi(2)).sqrt() / 2.0) as f32;
+        // The projection needs a near plane in front of the camera even when
+        // the camera is inside the part or the part has no size at all.
+        let floor = (radius * 1e-4).max(MIN_ZNEAR);
+        let znear = ((distance - radius).max(distance * 1e-3) * 0.9).max(floor);
+        let zfar = ((distance + radius) * 1.1).max(znear * 2.0);
+        (znear, zfar)
+    }
+
+    /// Returns a position above and in front of the part from the given
+    /// view.
+    pub fn pick_light_position(&self, view: &View) -> cgmath::Point3<f32> {
+        cgmath::Point3::from_vec(
+            view.direction * self.extent_along(view.direction)
+                + view.up * self.extent_along(view.up),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// How far the sample parts are moved, in both directions. Both are far
+    /// enough for the parts to get an origin of their own.
+    const DISTANCES: [f64; 4] = [1e6, -1e6, 1e9, -1e9];
+
+    /// Loads each sample part in `res`.
+    fn sample_parts() -> Vec<(String, Part)> {
+        let dir = concat!(env!("CARGO_MANIFEST_DIR"), "/res");
+        let mut paths = std::fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .filter(|path| path.extension() == Some("stl".as_ref()))
+            .collect::<Vec<_>>();
+        paths.sort();
+        assert!(!paths.is_empty());
+        paths
+            .into_iter()
+            .map(|path| {
+                let path = path.to_str().unwrap().to_string();
+                let part = crate::loader::load(&path, None, None).unwrap();
+                (path, part)
+            })
+            .collect()
+    }
+
+    /// Returns a copy of the part moved the given distance along every axis,
+    /// with its positions read in double precision the way a loader reads
+    /// them.
+    fn shifted(part: &Part, distance: f64) -> Part {
+        let positions = (0..part.positions.len() as u32)
+            .map(|vertex| {
+                let [x, y, z] = part.absolute_position(vertex);
+                [x + distance, y + distance, z + distance]
+            })
+            .collect::<Vec<_>>();
+        let mut shifted = Part {
+            positions: Vec::new(),
+            ..part.clone()
+        };
+        for &position in positions.iter() {
+            shifted.place_origin(position);
+        }
+        shifted.positions = positions
+            .into_iter()
+            .map(|position| shifted.local_position(position))
+            .collect();
+        shifted
+    }
+
+    fn assert_close(actual: f64, expected: f64, tolerance: f64, what: &str) {
+        assert!(
+            (actual - expected).abs() <= tolerance,
+            "{} is {} instead of {}",
+            what,
+            actual,
+            expected
+        );
+    }
+
+    #[test]
+    fn shifted_parts_keep_their_bounds_and_center() {
+        for (path, part) in sample_parts() {
+            let original = BoundingBox::new(&part);
+            let tolerance = (original.dx() + original.dy() + original.dz()) * 1e-5;
+            for &distance in DISTANCES.iter() {
+                let bounding_box = BoundingBox::new(&shifted(&part, distance));
+                let shift = to_render_space([distance; 3]);
+                let what = |name: &str| format!("{} of {} moved {}", name, path, distance);
+                for &(name, actual, expected) in [
+                    ("x_min", bounding_box.x_min, original.x_min + shift.x),
+                    ("x_max", bounding_box.x_max, original.x_max + shift.x),
+                    ("y_min", bounding_box.y_min, original.y_min + shift.y),
+                    ("y_max", bounding_box.y_max, original.y_max + shift.y),
+                    ("z_min", bounding_box.z_min, original.z_min + shift.z),
+                    ("z_max", bounding_box.z_max, original.z_max + shift.z),
+                ]
+                .iter()
+                {
+                    assert_close(actual, expected, tolerance, &what(name));
+                }
+
+                let center = bounding_box.center() - shift;
+                let expected = original.center();
+                assert_close(center.x, expected.x, tolerance, &what("Center x"));
+                assert_close(center.y, expected.y, tolerance, &what("Center y"));
+                assert_close(center.z, expected.z, tolerance, &what("Center z"));
+                assert_eq!(bounding_box.skipped_vertices, 0);
+            }
+        }
+    }
+
+    #[test]
+    fn shifted_parts_recenter_to_the_same_positions() {
+        for (path, part) in sample_parts() {
+            let mut original = part.clone();
+            let original_center = original.recenter();
+            let original_box = BoundingBox::new(&original);
+            let tolerance = (original_box.dx() + original_box.dy() + original_box.dz()) * 1e-5;
+            for &distance in DISTANCES.iter() {
+                let mut moved = shifted(&part, distance);
+                let center = moved.recenter();
+                let what = |name: &str| format!("{} of {} moved {}", name, path, distance);
+                for axis in 0..3 {
+                    assert_close(
+                        center[axis] - distance,
+                        original_center[axis],
+                        tolerance,
+                        &what("Center"),
+                    );
+                }
+                assert_eq!(moved.origin, [0.0; 3]);
+                for (moved, original) in moved.positions.iter().zip(original.positions.iter()) {
+                    for (&moved, &original) in moved.iter().zip(original.iter()) {
+                        assert_close(moved as f64, original as f64, tolerance, &what("Vertex"));
+                    }
+                }
+            }
         }
     }
 
-    pub fn pick_light_position(&self, axis: &Axis) -> cgmath::Point3<f32> {
-        match axis {
-            Axis::X => cgmath::Point3::new(self.dx(), self.dy(), 0.0),
-            Axis::Y => cgmath::Point3::new(0.0, self.dy(), -1.0 * self.dz()),
-            Axis::Z => cgmath::Point3::new(0.0, self.dy(), self.dz()),
+    #[test]
+    fn vertices_that_are_not_finite_are_skipped() {
+        for (path, part) in sample_parts() {
+            let original = BoundingBox::new(&part);
+            let mut part = shifted(&part, 1e6);
+            let expected = BoundingBox::new(&part);
+            part.positions.push([f32::NAN, 0.0, 0.0]);
+            part.positions.push([0.0, f32::INFINITY, f32::NEG_INFINITY]);
+            let bounding_box = BoundingBox::new(&part);
+            assert_eq!(bounding_box.skipped_vertices, 2, "{}", path);
+            assert_eq!(bounding_box.x_min, expected.x_min, "{}", path);
+            assert_eq!(bounding_box.x_max, expected.x_max, "{}", path);
+            assert_eq!(bounding_box.y_min, expected.y_min, "{}", path);
+            assert_eq!(bounding_box.y_max, expected.y_max, "{}", path);
+            assert_eq!(bounding_box.z_min, expected.z_min, "{}", path);
+            assert_eq!(bounding_box.z_max, expected.z_max, "{}", path);
+            // The part no longer reaches the origin, so its box mustn't either.
+            assert!(bounding_box.x_min > original.x_max, "{}", path);
         }
     }
 }
@@ -1,4 +1,4 @@
-use cgmath::{Matrix4, Point3, Rad, Vector3};
+use cgmath::{Deg, Matrix4, Point3, Vector3};
 use wgpu::util::DeviceExt;
 
 /// Uniform data that can be sent to the shaders. Contains the camera position
@@ -50,21 +50,48 @@ pub struct Camera {
     pub bind_group: wgpu::BindGroup,
 }
 
+/// How the camera projects the scene onto the image.
+#[derive(Clone, Copy, Debug)]
+pub enum Projection {
+    Perspective {
+        fovy: Deg<f32>,
+        aspect: f32,
+    },
+    /// A parallel projection that shows the given width and height of the
+    /// scene, so that parts look the same size however far away they are.
+    Orthographic {
+        width: f32,
+        height: f32,
+    },
+}
+
 impl Camera {
-    pub fn new_perspective_camera<P: Into<Point3<f32>>, A: Into<Rad<f32>>>(
+    pub fn new<P: Into<Point3<f32>>>(
         device: &wgpu::Device,
         position: P,
         target: P,
-        aspect: f32,
-        fovy: A,
+        up: Vector3<f32>,
+        projection: Projection,
         znear: f32,
         zfar: f32,
     ) -> Self {
         let position: Point3<f32> = position.into();
         let target: Point3<f32> = target.into();
 
-        let inv_transf_matrix = Matrix4::look_at_rh(position, target, Vector3::unit_y());
-        let projection_matrix = cgmath::perspective(fovy, aspect, znear, zfar);
+        let inv_transf_matrix = Matrix4::look_at_rh(position, target, up);
+        let projection_matrix = match projection {
+            Projection::Perspective { fovy, aspect } => {
+                cgmath::perspective(fovy, aspect, znear, zfar)
+            }
+            Projection::Orthographic { width, height } => cgmath::ortho(
+                -width / 2.0,
+                width / 2.0,
+                -height / 2.0,
+                height / 2.0,
+                znear,
+                zfar,
+            ),
+        };
         let view_transf_matrix = OPENGL_TO_WGPU_MATRIX * projection_matrix * inv_transf_matrix;
 
         let mut uniform = CameraUniforms::new();
@@ -74,7 +101,7 @@ impl Camera {
         );
 
         let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-            label: Some("Perspective Camera Uniform Buffer"),
+            label: Some("Camera Uniform Buffer"),
             contents: bytemuck::cast_slice(&[uniform]),
             usage: wgpu::BufferUsage::UNIFORM | wgpu::BufferUsage::COPY_DST,
         });
@@ -90,7 +117,7 @@ impl Camera {
                 },
                 count: None,
             }],
-            label: Some("Perspective Camera Bind Group Layout"),
+            label: Some("Camera Bind Group Layout"),
         });
 
         let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
@@ -99,7 +126,7 @@ impl Camera {
                 binding: 0,
                 resource: buffer.as_entire_binding(),
             }],
-            label: Some("Perspective Camera Bind Group"),
+            label: Some("Camera Bind Group"),
         });
 
         Self {
@@ -0,0 +1,182 @@
+use crate::part::color::linear_to_srgb;
+use crate::part::Part;
+use image::{Rgba, RgbaImage};
+
+/// Width of a glyph in the font, in font pixels.
+const GLYPH_WIDTH: u32 = 5;
+
+/// Height of a glyph in the font, in font pixels.
+const GLYPH_HEIGHT: u32 = 7;
+
+/// Space between the edge of the image, the edge of the legend and its lines,
+/// in font pixels.
+const PADDING: u32 = 3;
+
+/// A 5x7 font for the printable ASCII characters, starting with the space.
+/// Each glyph is five columns, and the lowest bit of each column is its top
+/// pixel.
+#[rustfmt::skip]
+const FONT: [[u8; 5]; 95] = [
+    [0x00, 0x00, 0x00, 0x00, 0x00], [0x00, 0x00, 0x5f, 0x00, 0x00],
+    [0x00, 0x07, 0x00, 0x07, 0x00], [0x14, 0x7f, 0x14, 0x7f, 0x14],
+    [0x24, 0x2a, 0x7f, 0x2a, 0x12], [0x23, 0x13, 0x08, 0x64, 0x62],
+    [0x36, 0x49, 0x56, 0x20, 0x50], [0x00, 0x05, 0x03, 0x00, 0x00],
+    [0x00, 0x1c, 0x22, 0x41, 0x00], [0x00, 0x41, 0x22, 0x1c, 0x00],
+    [0x14, 0x08, 0x3e, 0x08, 0x14], [0x08, 0x08, 0x3e, 0x08, 0x08],
+    [0x00, 0x50, 0x30, 0x00, 0x00], [0x08, 0x08, 0x08, 0x08, 0x08],
+    [0x00, 0x60, 0x60, 0x00, 0x00], [0x20, 0x10, 0x08, 0x04, 0x02],
+    [0x3e, 0x51, 0x49, 0x45, 0x3e], [0x00, 0x42, 0x7f, 0x40, 0x00],
+    [0x42, 0x61, 0x51, 0x49, 0x46], [0x21, 0x41, 0x45, 0x4b, 0x31],
+    [0x18, 0x14, 0x12, 0x7f, 0x10], [0x27, 0x45, 0x45, 0x45, 0x39],
+    [0x3c, 0x4a, 0x49, 0x49, 0x30], [0x01, 0x71, 0x09, 0x05, 0x03],
+    [0x36, 0x49, 0x49, 0x49, 0x36], [0x06, 0x49, 0x49, 0x29, 0x1e],
+    [0x00, 0x36, 0x36, 0x00, 0x00], [0x00, 0x56, 0x36, 0x00, 0x00],
+    [0x08, 0x14, 0x22, 0x41, 0x00], [0x14, 0x14, 0x14, 0x14, 0x14],
+    [0x00, 0x41, 0x22, 0x14, 0x08], [0x02, 0x01, 0x51, 0x09, 0x06],
+    [0x32, 0x49, 0x79, 0x41, 0x3e], [0x7e, 0x11, 0x11, 0x11, 0x7e],
+    [0x7f, 0x49, 0x49, 0x49, 0x36], [0x3e, 0x41, 0x41, 0x41, 0x22],
+    [0x7f, 0x41, 0x41, 0x22, 0x1c], [0x7f, 0x49, 0x49, 0x49, 0x41],
+    [0x7f, 0x09, 0x09, 0x09, 0x01], [0x3e, 0x41, 0x49, 0x49, 0x7a],
+    [0x7f, 0x08, 0x08, 0x08, 0x7f], [0x00, 0x41, 0x7f, 0x41, 0x00],
+    [0x20, 0x40, 0x41, 0x3f, 0x01], [0x7f, 0x08, 0x14, 0x22, 0x41],
+    [0x7f, 0x40, 0x40, 0x40, 0x40], [0x7f, 0x02, 0x0c, 0x02, 0x7f],
+    [0x7f, 0x04, 0x08, 0x10, 0x7f], [0x3e, 0x41, 0x41, 0x41, 0x3e],
+    [0x7f, 0x09, 0x09, 0x09, 0x06], [0x3e, 0x41, 0x51, 0x21, 0x5e],
+    [0x7f, 0x09, 0x19, 0x29, 0x46], [0x46, 0x49, 0x49, 0x49, 0x31],
+    [0x01, 0x01, 0x7f, 0x01, 0x01], [0x3f, 0x40, 0x40, 0x40, 0x3f],
+    [0x1f, 0x20, 0x40, 0x20, 0x1f], [0x3f, 0x40, 0x38, 0x40, 0x3f],
+    [0x63, 0x14, 0x08, 0x14, 0x63], [0x07, 0x08, 0x70, 0x08, 0x07],
+    [0x61, 0x51, 0x49, 0x45, 0x43], [0x00, 0x7f, 0x41, 0x41, 0x00],
+    [0x02, 0x04, 0x08, 0x10, 0x20], [0x00, 0x41, 0x41, 0x7f, 0x00],
+    [0x04, 0x02, 0x01, 0x02, 0x04], [0x40, 0x40, 0x40, 0x40, 0x40],
+    [0x00, 0x01, 0x02, 0x04, 0x00], [0x20, 0x54, 0x54, 0x54, 0x78],
+    [0x7f, 0x48, 0x44, 0x44, 0x38], [0x38, 0x44, 0x44, 0x44, 0x20],
+    [0x38, 0x44, 0x44, 0x48, 0x7f], [0x38, 0x54, 0x54, 0x54, 0x18],
+    [0x08, 0x7e, 0x09, 0x01, 0x02], [0x0c, 0x52, 0x52, 0x52, 0x3e],
+    [0x7f, 0x08, 0x04, 0x04, 0x78], [0x00, 0x44, 0x7d, 0x40, 0x00],
+    [0x20, 0x40, 0x44, 0x3d, 0x00], [0x7f, 0x10, 0x28, 0x44, 0x00],
+    [0x00, 0x41, 0x7f, 0x40, 0x00], [0x7c, 0x04, 0x18, 0x04, 0x78],
+    [0x7c, 0x08, 0x04, 0x04, 0x78], [0x38, 0x44, 0x44, 0x44, 0x38],
+    [0x7c, 0x14, 0x14, 0x14, 0x08], [0x08, 0x14, 0x14, 0x18, 0x7c],
+    [0x7c, 0x08, 0x04, 0x04, 0x08], [0x48, 0x54, 0x54, 0x54, 0x20],
+    [0x04, 0x3f, 0x44, 0x40, 0x20], [0x3c, 0x40, 0x40, 0x20, 0x7c],
+    [0x1c, 0x20, 0x40, 0x20, 0x1c], [0x3c, 0x40, 0x30, 0x40, 0x3c],
+    [0x44, 0x28, 0x10, 0x28, 0x44], [0x0c, 0x50, 0x50, 0x50, 0x3c],
+    [0x44, 0x64, 0x54, 0x4c, 0x44], [0x00, 0x08, 0x36, 0x41, 0x00],
+    [0x00, 0x00, 0x7f, 0x00, 0x00], [0x00, 0x41, 0x36, 0x08, 0x00],
+    [0x08, 0x04, 0x08, 0x10, 0x08],
+];
+
+/// Draws a legend in the top left corner of the image, with a line for each
+/// body of the part showing its color and name. Lines that don't fit in the
+/// image are left out.
+pub fn draw(image: &mut RgbaImage, part: &Part) {
+    // Each font pixel is drawn as a square of image pixels so that the text
+    // stays readable in large images.
+    let scale = (image.height() / 300).max(1);
+    let line_height = (GLYPH_HEIGHT + PADDING) * scale;
+    let advance = (GLYPH_WIDTH + 1) * scale;
+
+    let max_lines = (image.height().saturating_sub(3 * PADDING * scale) / line_height) as usize;
+    let max_chars = (image
+        .width()
+        .saturating_sub(4 * PADDING * scale + advance * 2)
+        / advance) as usize;
+    let lines = part
+        .bodies
+        .iter()
+        .enumerate()
+        .take(max_lines)
+        .map(|(i, body)| {
+            let name = body.name.chars().take(max_chars).collect::<String>();
+            (name, part.body_color(i as u32))
+        })
+        .collect::<Vec<_>>();
+    if lines.is_empty() {
+        return;
+    }
+
+    // Darken the area behind the legend so that it can be read against any
+    // background.
+    let longest = lines
+        .iter()
+        .map(|(name, _)| name.chars().count())
+        .max()
+        .unwrap() as u32;
+    let panel_width = 2 * PADDING * scale + advance * (longest + 2);
+    let panel_height = PADDING * scale + line_height * lines.len() as u32;
+    for y in PADDING * scale..PADDING * scale + panel_height {
+        for x in PADDING * scale..PADDING * scale + panel_width {
+            if let Some(pixel) = get_pixel_mut(image, x, y) {
+                for channel in pixel.0[..3].iter_mut() {
+                    *channel /= 2;
+                }
+            }
+        }
+    }
+
+    let white = Rgba([255, 255, 255, 255]);
+    for (i, (name, color)) in lines.iter().enumerate() {
+        let x = 2 * PADDING * scale;
+        let y = 2 * PADDING * scale + line_height * i as u32;
+
+        let swatch = Rgba([
+            (linear_to_srgb(color[0]) * 255.0).round() as u8,
+            (linear_to_srgb(color[1]) * 255.0).round() as u8,
+            (linear_to_srgb(color[2]) * 255.0).round() as u8,
+            255,
+        ]);
+        fill_rect(
+            image,
+            x,
+            y,
+            GLYPH_HEIGHT * scale,
+            GLYPH_HEIGHT * scale,
+            swatch,
+        );
+
+        for (j, c) in name.chars().enumerate() {
+            let glyph_x = x + advance * (j as u32 + 2);
+            draw_glyph(image, c, glyph_x, y, scale, white);
+        }
+    }
+}
+
+fn draw_glyph(image: &mut RgbaImage, c: char, x: u32, y: u32, scale: u32, color: Rgba<u8>) {
+    let index = match c {
+        ' '..='~' => c as usize - ' ' as usize,
+        _ => '?' as usize - ' ' as usize,
+    };
+    for (column, bits) in FONT[index].iter().enumerate() {
+        for row in 0..GLYPH_HEIGHT {
+            if bits & (1 << row) != 0 {
+                fill_rect(
+                    image,
+                    x + column as u32 * scale,
+                    y + row * scale,
+                    scale,
+                    scale,
+                    color,
+                );
+            }
+        }
+    }
+}
+
+fn fill_rect(image: &mut RgbaImage, x: u32, y: u32, width: u32, height: u32, color: Rgba<u8>) {
+    for y in y..y + height {
+        for x in x..x + width {
+            if let Some(pixel) = get_pixel_mut(image, x, y) {
+                *pixel = color;
+            }
+        }
+    }
+}
+
+fn get_pixel_mut(image: &mut RgbaImage, x: u32, y: u32) -> Option<&mut Rgba<u8>> {
+    if x < image.width() && y < image.height() {
+        Some(image.get_pixel_mut(x, y))
+    } else {
+        None
+    }
+}
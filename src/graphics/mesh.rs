@@ -1,4 +1,6 @@
+use crate::part::{Part, DEFAULT_COLOR};
 use anyhow::*;
+use std::borrow::Cow;
 use wgpu::util::DeviceExt;
 
 pub trait Vertex {
@@ -10,6 +12,7 @@ pub trait Vertex {
 pub struct MeshVertex {
     position: [f32; 3],
     normal: [f32; 3],
+    color: [f32; 3],
 }
 
 impl Vertex for MeshVertex {
@@ -31,55 +34,157 @@ impl Vertex for MeshVertex {
                     shader_location: 1,
                     format: wgpu::VertexFormat::Float3,
                 },
+                // Color
+                wgpu::VertexAttribute {
+                    offset: mem::size_of::<[f32; 6]>() as wgpu::BufferAddress,
+                    shader_location: 2,
+                    format: wgpu::VertexFormat::Float3,
+                },
             ],
         }
     }
 }
 
+/// The most bytes put in a single vertex or index buffer. wgpu 0.7 doesn't
+/// report the adapter's maximum buffer size in `wgpu::Limits`, so this is the
+/// smallest maximum that WebGPU allows adapters to have.
+const MAX_BUFFER_SIZE: usize = 256 << 20;
+
+/// Part of a mesh that fits in one vertex buffer and one index buffer, and is
+/// drawn with a single call.
 #[derive(Debug)]
-pub struct Mesh {
-    pub name: String,
+pub struct MeshChunk {
     pub vertex_buffer: wgpu::Buffer,
     pub index_buffer: wgpu::Buffer,
     pub num_indices: u32,
 }
 
+#[derive(Debug)]
+pub struct Mesh {
+    pub name: String,
+    pub chunks: Vec<MeshChunk>,
+}
+
 impl Mesh {
-    pub fn load(device: &wgpu::Device, mesh: &nom_stl::Mesh) -> Result<Self> {
-        let mut vertices = Vec::new();
-        for triangle in mesh.triangles() {
-            // TODO: Do I need to make sure thse are CCW around normal?
-            vertices.push(MeshVertex {
-                position: triangle.vertices()[0],
-                normal: triangle.normal(),
-            });
-            vertices.push(MeshVertex {
-                position: triangle.vertices()[1],
-                normal: triangle.normal(),
-            });
-            vertices.push(MeshVertex {
-                position: triangle.vertices()[2],
-                normal: triangle.normal(),
-            });
+    /// Upload a part to the GPU, splitting it across as many buffers as it
+    /// takes to keep each one within `MAX_BUFFER_SIZE`.
+    pub fn load(device: &wgpu::Device, part: &Part) -> Result<Self> {
+        let max_vertices = MAX_BUFFER_SIZE / std::mem::size_of::<MeshVertex>();
+        let max_indices = MAX_BUFFER_SIZE / std::mem::size_of::<u32>();
+        let chunks = split_into_chunks(part, max_vertices, max_indices)
+            .iter()
+            .map(|chunk| MeshChunk::load(device, part, chunk))
+            .collect::<Vec<_>>();
+        if chunks.len() > 1 {
+            log::info!("Split the mesh into {} chunks", chunks.len());
         }
 
-        let num_elements = vertices.len() as u32;
-        let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        Ok(Self {
+            name: "Mesh".to_string(),
+            chunks,
+        })
+    }
+}
+
+impl MeshChunk {
+    fn load(device: &wgpu::Device, part: &Part, chunk: &Chunk) -> Self {
+        let vertex_count = chunk
+            .vertices
+            .as_ref()
+            .map_or(part.positions.len(), Vec::len);
+
+        // Write the vertices straight into the mapped buffer, rather than
+        // building them up in a copy of their own first.
+        let vertex_size = std::mem::size_of::<MeshVertex>();
+        let vertex_buffer = device.create_buffer(&wgpu::BufferDescriptor {
             label: Some("Vertex Buffer"),
-            contents: bytemuck::cast_slice(&vertices),
+            // Mapped buffers can't be empty.
+            size: ((vertex_count * vertex_size) as wgpu::BufferAddress)
+                .max(wgpu::COPY_BUFFER_ALIGNMENT),
             usage: wgpu::BufferUsage::VERTEX,
+            mapped_at_creation: true,
         });
+        {
+            let mut mapped = vertex_buffer.slice(..).get_mapped_range_mut();
+            for (i, vertex) in mapped.chunks_exact_mut(vertex_size).enumerate() {
+                let i = chunk
+                    .vertices
+                    .as_ref()
+                    .map_or(i, |vertices| vertices[i] as usize);
+                vertex.copy_from_slice(bytemuck::bytes_of(&MeshVertex {
+                    position: part.positions[i],
+                    normal: part.normals[i],
+                    color: part
+                        .colors
+                        .as_ref()
+                        .map_or(DEFAULT_COLOR, |colors| colors[i]),
+                }));
+            }
+        }
+        vertex_buffer.unmap();
+
         let index_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
             label: Some("Index Buffer"),
-            contents: bytemuck::cast_slice(&(0..num_elements).collect::<Vec<u32>>()),
+            contents: bytemuck::cast_slice(&chunk.indices),
             usage: wgpu::BufferUsage::INDEX,
         });
 
-        Ok(Self {
-            name: "Mesh".to_string(),
+        Self {
             vertex_buffer,
             index_buffer,
-            num_indices: num_elements,
-        })
+            num_indices: chunk.indices.len() as u32,
+        }
+    }
+}
+
+/// The triangles of a part that go in one chunk of a mesh.
+struct Chunk<'a> {
+    /// The part's index of each vertex in the chunk, or `None` if the chunk
+    /// holds all of the part's vertices in order.
+    vertices: Option<Vec<u32>>,
+    /// The triangles, indexing the chunk's vertices.
+    indices: Cow<'a, [u32]>,
+}
+
+/// Splits the triangles of a part into chunks with no more than the given
+/// numbers of vertices and indices. A part that fits in one chunk isn't copied.
+fn split_into_chunks(part: &Part, max_vertices: usize, max_indices: usize) -> Vec<Chunk<'_>> {
+    if part.positions.len() <= max_vertices && part.indices.len() <= max_indices {
+        return vec![Chunk {
+            vertices: None,
+            indices: Cow::Borrowed(&part.indices),
+        }];
+    }
+
+    // The index of each of the part's vertices within the current chunk.
+    let mut local_indices = vec![None; part.positions.len()];
+    let mut chunks = Vec::new();
+    let mut vertices: Vec<u32> = Vec::new();
+    let mut indices = Vec::new();
+    for triangle in part.triangles() {
+        if vertices.len() + 3 > max_vertices || indices.len() + 3 > max_indices {
+            for &vertex in vertices.iter() {
+                local_indices[vertex as usize] = None;
+            }
+            chunks.push(Chunk {
+                vertices: Some(std::mem::take(&mut vertices)),
+                indices: Cow::Owned(std::mem::take(&mut indices)),
+            });
+        }
+
+        for &vertex in triangle.iter() {
+            let local = *local_indices[vertex as usize].get_or_insert_with(|| {
+                vertices.push(vertex);
+                vertices.len() as u32 - 1
+            });
+            indices.push(local);
+        }
+    }
+    if !indices.is_empty() {
+        chunks.push(Chunk {
+            vertices: Some(vertices),
+            indices: Cow::Owned(indices),
+        });
     }
+    chunks
 }
@@ -1,4 +1,5 @@
-mod camera;
+pub mod camera;
+mod legend;
 mod light;
 mod mesh;
 mod render_pipeline;
@@ -122,11 +122,15 @@ impl RenderPipeline {
             };
             let mut render_pass = encoder.begin_render_pass(&render_pass_desc);
             render_pass.set_pipeline(&self.pipeline);
-            render_pass.set_vertex_buffer(0, mesh.vertex_buffer.slice(..));
             render_pass.set_bind_group(0, model_transformation_bind_group, &[]);
             render_pass.set_bind_group(1, camera_bind_group, &[]);
             render_pass.set_bind_group(2, point_light_bind_group, &[]);
-            render_pass.draw(0..mesh.num_indices, 0..1);
+            for chunk in mesh.chunks.iter() {
+                render_pass.set_vertex_buffer(0, chunk.vertex_buffer.slice(..));
+                render_pass
+                    .set_index_buffer(chunk.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+                render_pass.draw_indexed(0..chunk.num_indices, 0, 0..1);
+            }
         }
 
         let u32_size = std::mem::size_of::<u32>() as u32;
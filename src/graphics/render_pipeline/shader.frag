@@ -13,6 +13,7 @@ layout(set = 2, binding = 0) uniform Light {
 
 layout(location=1) in vec3 v_normal; 
 layout(location=2) in vec3 v_position;
+layout(location=3) in vec3 v_color;
 
 layout(location=0) out vec4 f_color;
 
@@ -20,8 +21,7 @@ void main() {
     vec3 normal = normalize(v_normal);
     vec3 light_dir = normalize(light_position - v_position);
 
-    // TODO: Use input object color instead of hard-coded color.
-    vec4 object_color = vec4(1.0, 1.0, 1.0, 1.0);
+    vec4 object_color = vec4(v_color, 1.0);
 
     float ambient_strength = 0.05;
     vec3 ambient_color = light_color * ambient_strength;
@@ -13,9 +13,11 @@ uniform Camera {
 
 layout(location=0) in vec3 a_position;
 layout(location=1) in vec3 a_normal;
+layout(location=2) in vec3 a_color;
 
 layout(location=1) out vec3 v_normal;
 layout(location=2) out vec3 v_position;
+layout(location=3) out vec3 v_color;
 
 void main() {
     // TODO: This matrix math should be pulled out of the shader.
@@ -23,6 +25,7 @@ void main() {
     v_normal = normal_matrix * a_normal;
 
     v_position = a_position;
+    v_color = a_color;
 
     gl_Position = u_view_proj * u_model_transf * vec4(a_position, 1.0);
 }
\ No newline at end of file
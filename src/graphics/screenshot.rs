@@ -1,20 +1,31 @@
 use super::camera;
+use super::legend;
 use super::light;
 use super::mesh;
 use super::render_pipeline;
 use super::texture;
 use super::transformation;
+use crate::part::Part;
 use cgmath::Rotation3;
 
 pub struct ScreenshotDescriptor<'a> {
-    pub mesh: &'a nom_stl::Mesh,
+    pub part: &'a Part,
     pub dst_path: &'a str,
     pub width: u32,
     pub height: u32,
     pub model_translation: cgmath::Vector3<f32>,
     pub point_light_position: cgmath::Point3<f32>,
     pub camera_position: cgmath::Point3<f32>,
-    pub camera_fovy: cgmath::Deg<f32>,
+    pub camera_target: cgmath::Point3<f32>,
+    /// The direction that points up in the image.
+    pub camera_up: cgmath::Vector3<f32>,
+    pub camera_projection: camera::Projection,
+    pub camera_znear: f32,
+    pub camera_zfar: f32,
+    /// Whether to draw a legend of the part's bodies over the image.
+    pub legend: bool,
+    /// Keywords and text to store in the image, if it's a PNG.
+    pub metadata: Vec<(String, String)>,
 }
 
 /// Request the GPU device and its queue.
@@ -48,6 +59,9 @@ fn create_output_buffer(device: &wgpu::Device, width: u32, height: u32) -> wgpu:
     device.create_buffer(&output_buffer_desc)
 }
 
+/// The destination path that stands for standard output.
+pub const STDOUT_PATH: &str = "-";
+
 /// Poll data from the device and write the output buffer to the destination path.
 async fn save_buffer_to_image(
     device: &wgpu::Device,
@@ -55,6 +69,8 @@ async fn save_buffer_to_image(
     dst_path: &str,
     width: u32,
     height: u32,
+    legend_part: Option<&Part>,
+    metadata: &[(String, String)],
 ) {
     let buffer_slice = output_buffer.slice(..);
 
@@ -67,8 +83,56 @@ async fn save_buffer_to_image(
     let data = buffer_slice.get_mapped_range();
 
     use image::{ImageBuffer, Rgba};
-    let buffer = ImageBuffer::<Rgba<u8>, _>::from_raw(width, height, data).unwrap();
-    buffer.save(dst_path).unwrap();
+    let mut buffer = ImageBuffer::<Rgba<u8>, _>::from_raw(width, height, data.to_vec()).unwrap();
+    if let Some(part) = legend_part {
+        legend::draw(&mut buffer, part);
+    }
+    if dst_path == STDOUT_PATH {
+        // There's no extension to pick the image format from, so write a PNG.
+        let stdout = std::io::stdout();
+        write_png(stdout.lock(), &buffer, width, height, metadata).unwrap();
+    } else if is_png(dst_path) {
+        let file = std::fs::File::create(dst_path).unwrap();
+        write_png(
+            std::io::BufWriter::new(file),
+            &buffer,
+            width,
+            height,
+            metadata,
+        )
+        .unwrap();
+    } else {
+        if !metadata.is_empty() {
+            log::warn!("Metadata can only be stored in PNG images");
+        }
+        buffer.save(dst_path).unwrap();
+    }
+}
+
+fn is_png(path: &str) -> bool {
+    std::path::Path::new(path)
+        .extension()
+        .map_or(false, |extension| extension.eq_ignore_ascii_case("png"))
+}
+
+/// Encode RGBA pixels as a PNG, with each metadata entry stored as a text
+/// chunk.
+fn write_png<W: std::io::Write>(
+    writer: W,
+    pixels: &[u8],
+    width: u32,
+    height: u32,
+    metadata: &[(String, String)],
+) -> Result<(), png::EncodingError> {
+    let mut encoder = png::Encoder::new(writer, width, height);
+    encoder.set_color(png::ColorType::RGBA);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header()?;
+    for (keyword, text) in metadata.iter() {
+        let chunk = [keyword.as_bytes(), &[0], text.as_bytes()].concat();
+        writer.write_chunk(*b"tEXt", &chunk)?;
+    }
+    writer.write_image_data(pixels)
 }
 
 /// Generate a screenshot.
@@ -88,14 +152,14 @@ pub async fn run(screenshot_desc: ScreenshotDescriptor<'_>) {
         screenshot_desc.model_translation,
     );
 
-    let camera = camera::Camera::new_perspective_camera(
+    let camera = camera::Camera::new(
         &device,
         screenshot_desc.camera_position,
-        cgmath::Point3::new(0.0, 0.0, 0.0),
-        screenshot_desc.width as f32 / screenshot_desc.height as f32,
-        screenshot_desc.camera_fovy,
-        0.1,
-        1000.0,
+        screenshot_desc.camera_target,
+        screenshot_desc.camera_up,
+        screenshot_desc.camera_projection,
+        screenshot_desc.camera_znear,
+        screenshot_desc.camera_zfar,
     );
 
     let point_light = light::PointLight::new(
@@ -121,7 +185,7 @@ pub async fn run(screenshot_desc: ScreenshotDescriptor<'_>) {
         output_texture.desc.format,
     );
 
-    let mesh = mesh::Mesh::load(&device, screenshot_desc.mesh).unwrap();
+    let mesh = mesh::Mesh::load(&device, screenshot_desc.part).unwrap();
     render_pipeline.render(
         &device,
         &queue,
@@ -141,6 +205,8 @@ pub async fn run(screenshot_desc: ScreenshotDescriptor<'_>) {
         screenshot_desc.dst_path,
         screenshot_desc.width,
         screenshot_desc.height,
+        Some(screenshot_desc.part).filter(|_| screenshot_desc.legend),
+        &screenshot_desc.metadata,
     )
     .await;
     output_buffer.unmap();
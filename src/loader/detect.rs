@@ -0,0 +1,211 @@
+use std::fmt;
+
+/// The size of the header and triangle count at the start of a binary STL.
+pub const BINARY_STL_HEADER_SIZE: usize = 84;
+
+/// The size of each triangle record in a binary STL.
+pub const BINARY_STL_TRIANGLE_SIZE: usize = 50;
+
+/// How many bytes of a text file are looked at when guessing its format.
+const TEXT_SNIFF_SIZE: usize = 4096;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Format {
+    BinaryStl,
+    AsciiStl,
+    Obj,
+    Ply,
+    ThreeMf,
+    Gltf,
+    Glb,
+}
+
+impl Format {
+    /// Every format, in the order that they are checked when sniffing.
+    pub const ALL: [Format; 7] = [
+        Format::Glb,
+        Format::ThreeMf,
+        Format::Ply,
+        Format::BinaryStl,
+        Format::AsciiStl,
+        Format::Gltf,
+        Format::Obj,
+    ];
+
+    /// The names accepted by `from_name`.
+    pub const NAMES: [&'static str; 6] = ["stl", "obj", "ply", "3mf", "gltf", "glb"];
+
+    /// Returns the format with the given name, which is also the file
+    /// extension that usually indicates it.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            // An STL that looked like neither kind is most likely a
+            // truncated binary STL.
+            "stl" => Some(Format::BinaryStl),
+            "obj" => Some(Format::Obj),
+            "ply" => Some(Format::Ply),
+            "3mf" => Some(Format::ThreeMf),
+            "gltf" => Some(Format::Gltf),
+            "glb" => Some(Format::Glb),
+            _ => None,
+        }
+    }
+
+    /// Returns true if the contents look like a file of this format.
+    pub fn matches(&self, bytes: &[u8]) -> bool {
+        match self {
+            Format::Glb => bytes.starts_with(b"glTF"),
+            Format::ThreeMf => is_3mf(bytes),
+            Format::Ply => bytes.starts_with(b"ply\n") || bytes.starts_with(b"ply\r\n"),
+            Format::BinaryStl => is_binary_stl(bytes),
+            Format::AsciiStl => is_ascii_stl(bytes),
+            Format::Gltf => is_gltf(bytes),
+            Format::Obj => is_obj(bytes),
+        }
+    }
+}
+
+impl fmt::Display for Format {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Format::BinaryStl => "binary STL",
+            Format::AsciiStl => "ASCII STL",
+            Format::Obj => "OBJ",
+            Format::Ply => "PLY",
+            Format::ThreeMf => "3MF",
+            Format::Gltf => "glTF",
+            Format::Glb => "GLB",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Returns the format of a file by looking at its contents.
+pub fn sniff(bytes: &[u8]) -> Option<Format> {
+    Format::ALL
+        .iter()
+        .copied()
+        .find(|format| format.matches(bytes))
+}
+
+/// Returns the start of the contents as text, skipping a UTF-8 byte order
+/// mark and leading whitespace.
+fn leading_text(bytes: &[u8]) -> &[u8] {
+    let bytes = &bytes[..bytes.len().min(TEXT_SNIFF_SIZE)];
+    let bytes = bytes.strip_prefix(b"\xEF\xBB\xBF").unwrap_or(bytes);
+    let start = bytes
+        .iter()
+        .position(|b| !b.is_ascii_whitespace())
+        .unwrap_or(bytes.len());
+    &bytes[start..]
+}
+
+/// A binary STL is identified by its size, which the triangle count in its
+/// header determines. Some exporters pad the end of the file, so a longer file
+/// is accepted too unless it starts like an ASCII STL.
+fn is_binary_stl(bytes: &[u8]) -> bool {
+    if bytes.len() < BINARY_STL_HEADER_SIZE {
+        return false;
+    }
+    let mut count = [0u8; 4];
+    count.copy_from_slice(&bytes[80..84]);
+    let expected_size = (u32::from_le_bytes(count) as u64)
+        .saturating_mul(BINARY_STL_TRIANGLE_SIZE as u64)
+        .saturating_add(BINARY_STL_HEADER_SIZE as u64);
+    let size = bytes.len() as u64;
+    size == expected_size || (size > expected_size && !is_ascii_stl(bytes))
+}
+
+fn is_ascii_stl(bytes: &[u8]) -> bool {
+    let text = leading_text(bytes);
+    text.starts_with(b"solid")
+        && (contains(text, b"facet") || contains(text, b"endsolid"))
+        && !text.contains(&0)
+}
+
+/// 3MF packages are zip archives. The first entry is almost always the content
+/// types or the model itself, so its name is in the first local file header.
+fn is_3mf(bytes: &[u8]) -> bool {
+    let head = &bytes[..bytes.len().min(TEXT_SNIFF_SIZE)];
+    head.starts_with(b"PK\x03\x04")
+        && (contains(head, b"3dmodel") || contains(head, b"[Content_Types].xml"))
+}
+
+fn is_gltf(bytes: &[u8]) -> bool {
+    let text = leading_text(bytes);
+    text.starts_with(b"{") && contains(text, b"\"asset\"")
+}
+
+/// OBJ files have no header, so they are recognized by most of their leading
+/// lines being OBJ statements, with at least one vertex among them.
+fn is_obj(bytes: &[u8]) -> bool {
+    let text = leading_text(bytes);
+    let text = match std::str::from_utf8(text) {
+        Ok(text) => text,
+        // The sniffed region might end in the middle of a character.
+        Err(err) => std::str::from_utf8(&text[..err.valid_up_to()]).unwrap(),
+    };
+
+    let mut statements = 0;
+    let mut vertices = 0;
+    let mut other = 0;
+    for line in text.lines() {
+        match line.split_whitespace().next() {
+            None => {}
+            Some(keyword) if keyword.starts_with('#') => {}
+            Some("v") => {
+                vertices += 1;
+                statements += 1;
+            }
+            Some("vn") | Some("vt") | Some("vp") | Some("f") | Some("l") | Some("p")
+            | Some("o") | Some("g") | Some("s") | Some("usemtl") | Some("mtllib") => {
+                statements += 1
+            }
+            Some(_) => other += 1,
+        }
+    }
+    vertices > 0 && statements > other
+}
+
+fn contains(haystack: &[u8], needle: &[u8]) -> bool {
+    haystack
+        .windows(needle.len())
+        .any(|window| window == needle)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formats_are_sniffed_from_their_contents() {
+        assert_eq!(sniff(b"glTF\x02\x00\x00\x00"), Some(Format::Glb));
+        assert_eq!(
+            sniff(b"PK\x03\x04\x14\x00\x00\x00\x00\x003D/3dmodel.model"),
+            Some(Format::ThreeMf)
+        );
+        assert_eq!(sniff(b"ply\nformat ascii 1.0\n"), Some(Format::Ply));
+        assert_eq!(
+            sniff(b"\xEF\xBB\xBF solid part\nfacet normal 0 0 1\n"),
+            Some(Format::AsciiStl)
+        );
+        assert_eq!(
+            sniff(b"{ \"asset\": { \"version\": \"2.0\" } }"),
+            Some(Format::Gltf)
+        );
+        assert_eq!(
+            sniff(b"# exported\nv 0 0 0\nv 1 0 0\nv 0 1 0\nf 1 2 3\n"),
+            Some(Format::Obj)
+        );
+        assert_eq!(sniff(b"hello world"), None);
+    }
+
+    #[test]
+    fn binary_stl_may_start_with_solid() {
+        let mut bytes = b"solid exported by a careless program".to_vec();
+        bytes.resize(80, 0);
+        bytes.extend_from_slice(&1u32.to_le_bytes());
+        bytes.resize(BINARY_STL_HEADER_SIZE + BINARY_STL_TRIANGLE_SIZE, 0);
+        assert_eq!(sniff(&bytes), Some(Format::BinaryStl));
+    }
+}
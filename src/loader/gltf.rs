@@ -0,0 +1,356 @@
+use super::Input;
+use crate::part::{face_normal, Part};
+use crate::units::Unit;
+use anyhow::{ensure, Context, Result};
+use cgmath::{InnerSpace, Matrix, Matrix3, Matrix4, Point3, SquareMatrix, Transform, Vector3};
+use std::path::Path;
+
+/// Load a glTF 2.0 file, either as JSON with external or data URI buffers, or
+/// as a binary GLB. Every node with a mesh becomes a body of the part, placed
+/// with the combined transforms of its ancestors and colored from the base
+/// color factor of its materials. glTF scenes are Y-up and measured in metres,
+/// so they're turned Z-up and scaled to millimetres like every other part.
+pub fn load(input: &Input) -> Result<Part> {
+    let ::gltf::Gltf { document, blob } =
+        ::gltf::Gltf::from_slice(&input.bytes).context("Unable to parse glTF")?;
+    let buffers = load_buffers(&document, input.dir.as_deref(), blob)
+        .context("Unable to load glTF buffers")?;
+
+    let mut part = Part {
+        unit: Some(Unit::Millimetre),
+        ..Part::new()
+    };
+    match document
+        .default_scene()
+        .or_else(|| document.scenes().next())
+    {
+        Some(scene) => {
+            for node in scene.nodes() {
+                add_node(&mut part, &buffers, &node, to_part_space())?;
+            }
+        }
+        // Without a scene there's no node hierarchy to place the meshes with.
+        None => {
+            for mesh in document.meshes() {
+                let name = mesh
+                    .name()
+                    .map(str::to_string)
+                    .unwrap_or_else(|| format!("Mesh {}", mesh.index()));
+                let body = part.add_body(&name);
+                add_mesh(&mut part, &buffers, &mesh, to_part_space(), body)?;
+            }
+        }
+    }
+
+    Ok(part)
+}
+
+/// Returns the transform from glTF's Y-up metres, with the front of the scene
+/// facing +Z, to Z-up millimetres with the front facing -Y.
+fn to_part_space() -> Matrix4<f64> {
+    #[rustfmt::skip]
+    let y_up_to_z_up = Matrix4::new(
+        1.0, 0.0, 0.0, 0.0,
+        0.0, 0.0, 1.0, 0.0,
+        0.0, -1.0, 0.0, 0.0,
+        0.0, 0.0, 0.0, 1.0,
+    );
+    Matrix4::from_scale(1000.0) * y_up_to_z_up
+}
+
+/// Returns the contents of each buffer in the document.
+fn load_buffers(
+    document: &::gltf::Document,
+    base: Option<&Path>,
+    mut blob: Option<Vec<u8>>,
+) -> Result<Vec<Vec<u8>>> {
+    document
+        .buffers()
+        .map(|buffer| {
+            let data = match buffer.source() {
+                ::gltf::buffer::Source::Bin => blob.take().context("GLB has no binary chunk")?,
+                ::gltf::buffer::Source::Uri(uri) if uri.starts_with("data:") => {
+                    let (header, data) = uri.split_once(',').with_context(|| {
+                        format!("Invalid data URI in buffer {}", buffer.index())
+                    })?;
+                    ensure!(
+                        header.ends_with(";base64"),
+                        "Buffer {} is not base64 encoded",
+                        buffer.index()
+                    );
+                    base64::decode(data)?
+                }
+                ::gltf::buffer::Source::Uri(uri) => {
+                    let buffer_path = base.map_or_else(|| uri.into(), |base| base.join(uri));
+                    std::fs::read(&buffer_path)
+                        .with_context(|| format!("Unable to read {}", buffer_path.display()))?
+                }
+            };
+            ensure!(
+                data.len() >= buffer.length(),
+                "Buffer {} is shorter than its declared length",
+                buffer.index()
+            );
+            Ok(data)
+        })
+        .collect()
+}
+
+fn add_node(
+    part: &mut Part,
+    buffers: &[Vec<u8>],
+    node: &::gltf::Node,
+    parent_transform: Matrix4<f64>,
+) -> Result<()> {
+    // Transforms are combined in double precision, so that a mesh placed far
+    // from the origin keeps its detail until it's moved near it.
+    let transform = parent_transform
+        * Matrix4::from(node.transform().matrix())
+            .cast::<f64>()
+            .unwrap();
+
+    if let Some(mesh) = node.mesh() {
+        let name = node
+            .name()
+            .or_else(|| mesh.name())
+            .map(str::to_string)
+            .unwrap_or_else(|| format!("Node {}", node.index()));
+        let body = part.add_body(&name);
+        add_mesh(part, buffers, &mesh, transform, body)?;
+    }
+
+    for child in node.children() {
+        add_node(part, buffers, &child, transform)?;
+    }
+
+    Ok(())
+}
+
+fn add_mesh(
+    part: &mut Part,
+    buffers: &[Vec<u8>],
+    mesh: &::gltf::Mesh,
+    transform: Matrix4<f64>,
+    body: u32,
+) -> Result<()> {
+    let linear = Matrix3::from_cols(
+        transform.x.truncate(),
+        transform.y.truncate(),
+        transform.z.truncate(),
+    );
+    let normal_matrix = linear
+        .invert()
+        .map_or(linear, |inverse| inverse.transpose())
+        .cast::<f32>()
+        .unwrap();
+    // A mirroring transform turns counter-clockwise triangles clockwise.
+    let mirrored = linear.determinant() < 0.0;
+
+    for primitive in mesh.primitives() {
+        let corners = match triangle_corners(&primitive, buffers)? {
+            Some(corners) => corners,
+            // Points and lines have no surface to render.
+            None => continue,
+        };
+        let reader = primitive.reader(|buffer| buffers.get(buffer.index()).map(Vec::as_slice));
+
+        let positions = reader
+            .read_positions()
+            .context("glTF primitive has no positions")?
+            .map(|p| {
+                let p = Point3::from(p).cast::<f64>().unwrap();
+                transform.transform_point(p).into()
+            })
+            .collect::<Vec<[f64; 3]>>();
+        for &position in positions.iter() {
+            part.place_origin(position);
+        }
+        let positions = positions
+            .into_iter()
+            .map(|position| part.local_position(position))
+            .collect::<Vec<_>>();
+        let normals = reader.read_normals().map(|normals| {
+            normals
+                .map(|n| {
+                    let n = normal_matrix * Vector3::from(n);
+                    if n.magnitude2() > 0.0 {
+                        n.normalize().into()
+                    } else {
+                        n.into()
+                    }
+                })
+                .collect::<Vec<[f32; 3]>>()
+        });
+        let vertex_colors = reader
+            .read_colors(0)
+            .map(|colors| colors.into_rgb_f32().collect::<Vec<_>>());
+
+        // glTF colors are already linear, so they can be used as they are.
+        let factor = primitive
+            .material()
+            .pbr_metallic_roughness()
+            .base_color_factor();
+        let color = |i: usize| -> [f32; 3] {
+            let vertex = vertex_colors
+                .as_ref()
+                .and_then(|colors| colors.get(i))
+                .copied()
+                .unwrap_or([1.0, 1.0, 1.0]);
+            [
+                vertex[0] * factor[0],
+                vertex[1] * factor[1],
+                vertex[2] * factor[2],
+            ]
+        };
+
+        let mut triangles = Vec::with_capacity(corners.len());
+        for triangle in corners.iter() {
+            let mut triangle = *triangle;
+            if mirrored {
+                triangle.swap(1, 2);
+            }
+            for &i in triangle.iter() {
+                ensure!(
+                    i < positions.len(),
+                    "glTF primitive refers to missing vertex {}",
+                    i
+                );
+            }
+            triangles.push(triangle);
+        }
+
+        match normals {
+            Some(normals) if normals.len() == positions.len() => {
+                let first_vertex = part.positions.len() as u32;
+                for (i, (&position, &normal)) in positions.iter().zip(normals.iter()).enumerate() {
+                    part.add_colored_vertex(position, normal, color(i));
+                }
+                for triangle in triangles.iter() {
+                    part.add_triangle(
+                        [
+                            first_vertex + triangle[0] as u32,
+                            first_vertex + triangle[1] as u32,
+                            first_vertex + triangle[2] as u32,
+                        ],
+                        body,
+                    );
+                }
+            }
+            // Without vertex normals each triangle gets its own vertices so
+            // that it can be shaded flat.
+            _ => {
+                for triangle in triangles.iter() {
+                    let vertices = [
+                        positions[triangle[0]],
+                        positions[triangle[1]],
+                        positions[triangle[2]],
+                    ];
+                    let normal = face_normal(&vertices);
+                    let mut indices = [0; 3];
+                    for (index, &i) in indices.iter_mut().zip(triangle.iter()) {
+                        *index = part.add_colored_vertex(positions[i], normal, color(i));
+                    }
+                    part.add_triangle(indices, body);
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Returns the vertex indices of each triangle in the primitive, or `None` if
+/// the primitive is made of points or lines.
+fn triangle_corners(
+    primitive: &::gltf::Primitive,
+    buffers: &[Vec<u8>],
+) -> Result<Option<Vec<[usize; 3]>>> {
+    let reader = primitive.reader(|buffer| buffers.get(buffer.index()).map(Vec::as_slice));
+    let indices = match reader.read_indices() {
+        Some(indices) => indices.into_u32().map(|i| i as usize).collect::<Vec<_>>(),
+        None => {
+            let count = reader
+                .read_positions()
+                .context("glTF primitive has no positions")?
+                .len();
+            (0..count).collect()
+        }
+    };
+
+    use ::gltf::mesh::Mode;
+    Ok(match primitive.mode() {
+        Mode::Triangles => Some(
+            indices
+                .chunks_exact(3)
+                .map(|t| [t[0], t[1], t[2]])
+                .collect(),
+        ),
+        Mode::TriangleStrip => Some(
+            indices
+                .windows(3)
+                .enumerate()
+                .map(|(i, t)| {
+                    if i % 2 == 0 {
+                        [t[0], t[1], t[2]]
+                    } else {
+                        [t[1], t[0], t[2]]
+                    }
+                })
+                .collect(),
+        ),
+        Mode::TriangleFan => Some(
+            indices
+                .iter()
+                .skip(1)
+                .zip(indices.iter().skip(2))
+                .map(|(&b, &c)| [indices[0], b, c])
+                .collect(),
+        ),
+        Mode::Points | Mode::Lines | Mode::LineLoop | Mode::LineStrip => None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A triangle in the XY plane, one metre across, under a child node that
+    /// is moved one metre along +Z inside a parent moved one metre along +X.
+    const SCENE: &str = r#"{
+        "asset": { "version": "2.0" },
+        "scene": 0,
+        "scenes": [{ "nodes": [0] }],
+        "nodes": [
+            { "translation": [1, 0, 0], "children": [1] },
+            { "translation": [0, 0, 1], "mesh": 0 }
+        ],
+        "meshes": [{ "primitives": [{ "attributes": { "POSITION": 0 } }] }],
+        "accessors": [{
+            "bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3",
+            "min": [0, 0, 0], "max": [1, 1, 0]
+        }],
+        "bufferViews": [{ "buffer": 0, "byteLength": 36 }],
+        "buffers": [{
+            "byteLength": 36,
+            "uri": "data:application/octet-stream;base64,AAAAAAAAAAAAAAAAAACAPwAAAAAAAAAAAAAAAAAAgD8AAAAA"
+        }]
+    }"#;
+
+    #[test]
+    fn nodes_are_placed_z_up_in_millimetres() {
+        let part = load(&Input::from_test_bytes(SCENE.as_bytes())).unwrap();
+
+        assert_eq!(part.triangle_count(), 1);
+        let triangle = part.triangles().next().unwrap();
+        assert_eq!(
+            part.triangle_positions(triangle),
+            [
+                [1000.0, -1000.0, 0.0],
+                [2000.0, -1000.0, 0.0],
+                [1000.0, -1000.0, 1000.0]
+            ]
+        );
+        // The triangle faced the front of the scene, +Z, so it now faces -Y.
+        assert_eq!(part.normals[triangle[0] as usize], [0.0, -1.0, 0.0]);
+    }
+}
@@ -0,0 +1,248 @@
+use super::detect::Format;
+use anyhow::{bail, Context, Result};
+use std::io::{BufRead, BufReader, Cursor, Read, Seek};
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+
+/// The path that stands for standard input.
+pub const STDIN_PATH: &str = "-";
+
+/// Separates the path of a zip archive from the path of a file inside it, as
+/// in `archive.zip!inner/path.stl`.
+const ARCHIVE_SEPARATOR: char = '!';
+
+/// Compressed files may themselves contain compressed files, but there's no
+/// reason for them to be nested deeper than this.
+const MAX_COMPRESSION_DEPTH: usize = 4;
+
+const GZIP_MAGIC: &[u8] = &[0x1f, 0x8b];
+const ZSTD_MAGIC: &[u8] = &[0x28, 0xb5, 0x2f, 0xfd];
+const XZ_MAGIC: &[u8] = &[0xfd, b'7', b'z', b'X', b'Z', 0x00];
+
+/// The raw contents of an input file. Files that are neither compressed nor
+/// in an archive are mapped into memory rather than read, so that huge parts
+/// don't need a copy of the whole file on the heap.
+pub enum Bytes {
+    Read(Vec<u8>),
+    Mapped(memmap2::Mmap),
+}
+
+impl Deref for Bytes {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self {
+            Bytes::Read(bytes) => bytes,
+            Bytes::Mapped(map) => map,
+        }
+    }
+}
+
+/// The contents of an input, along with where it came from.
+pub struct Input {
+    /// Where the input came from, for use in messages.
+    pub name: String,
+    pub bytes: Bytes,
+    /// The directory that relative references, like external glTF buffers,
+    /// are resolved against.
+    pub dir: Option<PathBuf>,
+}
+
+impl Input {
+    /// Reads the file at the given path, which may refer to a file inside a
+    /// zip archive with `archive.zip!inner/path.stl`, or to standard input
+    /// with `-`. Gzip, zstd and xz compressed files are decompressed as they
+    /// are read.
+    pub fn read(path: &str) -> Result<Self> {
+        if path == STDIN_PATH {
+            let stdin = std::io::stdin();
+            let bytes = read_decompressed(stdin.lock()).context("Unable to read standard input")?;
+            return Self::from_bytes("standard input".to_string(), Bytes::Read(bytes), None);
+        }
+
+        if !Path::new(path).exists() {
+            if let Some((archive_path, entry)) = path.split_once(ARCHIVE_SEPARATOR) {
+                let file = std::fs::File::open(archive_path)
+                    .with_context(|| format!("Unable to open {}", archive_path))?;
+                return Self::read_archive_entry(archive_path, BufReader::new(file), Some(entry));
+            }
+        }
+
+        let file = std::fs::File::open(path).with_context(|| format!("Unable to open {}", path))?;
+        let dir = Path::new(path).parent().map(Path::to_path_buf);
+        let mut reader = BufReader::new(file);
+        let header = reader
+            .fill_buf()
+            .with_context(|| format!("Unable to read {}", path))?;
+        // Empty files can't be mapped.
+        if !header.is_empty() && !is_compressed(header) {
+            // Safety: the map is only read, and we have to trust that nothing
+            // truncates the file while it's being loaded.
+            let map = unsafe { memmap2::Mmap::map(reader.get_ref()) }
+                .with_context(|| format!("Unable to read {}", path))?;
+            return Self::from_bytes(path.to_string(), Bytes::Mapped(map), dir);
+        }
+
+        let bytes =
+            read_decompressed(reader).with_context(|| format!("Unable to read {}", path))?;
+        Self::from_bytes(path.to_string(), Bytes::Read(bytes), dir)
+    }
+
+    fn from_bytes(name: String, bytes: Bytes, dir: Option<PathBuf>) -> Result<Self> {
+        // A zip archive that isn't a 3MF package is a bundle of parts.
+        if bytes.starts_with(b"PK\x03\x04") && !Format::ThreeMf.matches(&bytes) {
+            return Self::read_archive_entry(&name, Cursor::new(&bytes[..]), None);
+        }
+
+        Ok(Self { name, bytes, dir })
+    }
+
+    /// Reads a file from a zip archive. If no entry is given, the archive must
+    /// contain exactly one file.
+    fn read_archive_entry<R: Read + Seek>(
+        archive_name: &str,
+        reader: R,
+        entry: Option<&str>,
+    ) -> Result<Self> {
+        let mut archive = zip::ZipArchive::new(reader)
+            .with_context(|| format!("Unable to open zip archive {}", archive_name))?;
+
+        let entry = match entry {
+            Some(entry) => entry.to_string(),
+            None => {
+                let files = archive
+                    .file_names()
+                    .filter(|name| !name.ends_with('/'))
+                    .collect::<Vec<_>>();
+                match files.as_slice() {
+                    [file] => file.to_string(),
+                    _ => bail!(
+                        "{} contains {} files. Choose one with <ARCHIVE>{}<FILE>. The files are: {}",
+                        archive_name,
+                        files.len(),
+                        ARCHIVE_SEPARATOR,
+                        files.join(", ")
+                    ),
+                }
+            }
+        };
+
+        let name = format!("{}{}{}", archive_name, ARCHIVE_SEPARATOR, entry);
+        let zip_file = archive
+            .by_name(&entry)
+            .with_context(|| format!("{} has no file named {}", archive_name, entry))?;
+        let bytes = read_decompressed(BufReader::new(zip_file))
+            .with_context(|| format!("Unable to read {}", name))?;
+
+        Ok(Self {
+            name,
+            bytes: Bytes::Read(bytes),
+            // References can't be resolved within the archive.
+            dir: None,
+        })
+    }
+
+    /// Returns the file extension that indicates the format of the input,
+    /// ignoring any compression extension.
+    pub fn extension(&self) -> Option<String> {
+        let file_name = self.name.rsplit(&['/', '\\'][..]).next()?;
+        let mut parts = file_name.split('.').skip(1).collect::<Vec<_>>();
+        while let Some(extension) = parts.last() {
+            match extension.to_ascii_lowercase().as_str() {
+                "gz" | "zst" | "xz" => {
+                    parts.pop();
+                }
+                _ => break,
+            }
+        }
+        parts.last().map(|extension| extension.to_string())
+    }
+}
+
+#[cfg(test)]
+impl Input {
+    /// Wraps the contents of a file that was built in memory by a test.
+    pub fn from_test_bytes(bytes: &[u8]) -> Self {
+        Self {
+            name: "test input".to_string(),
+            bytes: Bytes::Read(bytes.to_vec()),
+            dir: None,
+        }
+    }
+}
+
+/// Returns whether the data starts with a gzip, zstd or xz header.
+fn is_compressed(header: &[u8]) -> bool {
+    header.starts_with(GZIP_MAGIC) || header.starts_with(ZSTD_MAGIC) || header.starts_with(XZ_MAGIC)
+}
+
+/// Reads the stream to the end, decompressing it if it starts with a gzip,
+/// zstd or xz header.
+fn read_decompressed<R: BufRead>(reader: R) -> Result<Vec<u8>> {
+    let mut reader: Box<dyn BufRead + '_> = Box::new(reader);
+    for _ in 0..MAX_COMPRESSION_DEPTH {
+        let header = reader.fill_buf()?;
+        reader = if header.starts_with(GZIP_MAGIC) {
+            log::info!("Decompressing gzip stream");
+            Box::new(BufReader::new(flate2::read::MultiGzDecoder::new(reader)))
+        } else if header.starts_with(ZSTD_MAGIC) {
+            log::info!("Decompressing zstd stream");
+            Box::new(BufReader::new(zstd::stream::read::Decoder::with_buffer(
+                reader,
+            )?))
+        } else if header.starts_with(XZ_MAGIC) {
+            log::info!("Decompressing xz stream");
+            Box::new(BufReader::new(xz2::read::XzDecoder::new_multi_decoder(
+                reader,
+            )))
+        } else {
+            break;
+        };
+    }
+
+    let mut bytes = Vec::new();
+    reader.read_to_end(&mut bytes)?;
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    const STL: &[u8] = b"solid part\nfacet normal 0 0 1\nendsolid part\n";
+
+    /// Writes a zip archive with the given files to a temporary path.
+    fn write_archive(name: &str, files: &[(&str, &[u8])]) -> String {
+        let path = std::env::temp_dir().join(name);
+        let mut zip = zip::ZipWriter::new(std::fs::File::create(&path).unwrap());
+        for (name, contents) in files {
+            zip.start_file(*name, zip::write::FileOptions::default())
+                .unwrap();
+            zip.write_all(contents).unwrap();
+        }
+        zip.finish().unwrap();
+        path.to_str().unwrap().to_string()
+    }
+
+    #[test]
+    fn compressed_archive_entries_are_read() {
+        let mut gzip = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::fast());
+        gzip.write_all(STL).unwrap();
+        let archive = write_archive(
+            "part_viewer_entries.zip",
+            &[
+                ("parts/part.stl.gz", &gzip.finish().unwrap()),
+                ("readme.txt", b"Not a part"),
+            ],
+        );
+
+        let input = Input::read(&format!("{}!parts/part.stl.gz", archive)).unwrap();
+        assert_eq!(&input.bytes[..], STL);
+        assert_eq!(input.extension().as_deref(), Some("stl"));
+        assert!(input.dir.is_none());
+
+        // With more than one file in the archive, one has to be chosen.
+        assert!(Input::read(&archive).is_err());
+    }
+}
@@ -0,0 +1,77 @@
+mod detect;
+mod gltf;
+mod input;
+mod obj;
+mod ply;
+mod stl;
+mod threemf;
+
+use crate::part::Part;
+use anyhow::{bail, Context, Result};
+use input::Input;
+
+pub use detect::Format;
+
+/// Load the part at the given path, which may be compressed, inside a zip
+/// archive or `-` for standard input. Unless a format is given, it's detected
+/// from the contents of the file, and the extension is only used when the
+/// contents don't match any format.
+///
+/// Given a triangle budget, formats that can be simplified while they're read
+/// may return a part with fewer triangles than the file, though still more
+/// than the budget.
+pub fn load(path: &str, format: Option<Format>, max_triangles: Option<usize>) -> Result<Part> {
+    let input = Input::read(path)?;
+    load_input(&input, format, max_triangles)
+}
+
+/// Load a part from the contents of a file. If no format is given it's
+/// detected from the contents.
+fn load_input(input: &Input, format: Option<Format>, max_triangles: Option<usize>) -> Result<Part> {
+    let format = match format {
+        // Whether an STL is binary or ASCII always comes from the contents.
+        Some(Format::BinaryStl) | Some(Format::AsciiStl) => {
+            if Format::AsciiStl.matches(&input.bytes) {
+                Format::AsciiStl
+            } else {
+                Format::BinaryStl
+            }
+        }
+        Some(format) => format,
+        None => detect_format(input)?,
+    };
+    log::info!("Loading {} as {}", input.name, format);
+
+    let part = match format {
+        Format::BinaryStl => stl::load_binary(input, max_triangles),
+        Format::AsciiStl => stl::load_ascii(input),
+        Format::Obj => obj::load(input),
+        Format::Ply => ply::load(input),
+        Format::ThreeMf => threemf::load(input),
+        Format::Gltf | Format::Glb => gltf::load(input),
+    };
+    part.with_context(|| format!("Unable to load {} as {}", input.name, format))
+}
+
+/// Returns the format of the input, going by its contents first and then its
+/// file extension.
+fn detect_format(input: &Input) -> Result<Format> {
+    let fallback = input
+        .extension()
+        .and_then(|extension| Format::from_name(&extension));
+    match detect::sniff(&input.bytes).or(fallback) {
+        Some(format) => Ok(format),
+        None => {
+            let tried = Format::ALL
+                .iter()
+                .map(|format| format.to_string())
+                .collect::<Vec<_>>()
+                .join(", ");
+            bail!(
+                "Unable to detect the format of {}. Supported formats are {}",
+                input.name,
+                tried
+            );
+        }
+    }
+}
@@ -0,0 +1,125 @@
+use super::Input;
+use crate::part::{face_normal, Part};
+use anyhow::{anyhow, Result};
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::io::Cursor;
+
+/// Load a Wavefront OBJ file. Each object and group in the file becomes a body
+/// of the part. Faces with more than three vertices are triangulated.
+pub fn load(input: &Input) -> Result<Part> {
+    let mut part = Part::new();
+    let bytes = relative_to_origin(&mut part, &input.bytes);
+    // Materials aren't used, so material libraries aren't loaded.
+    let (models, _materials) = tobj::load_obj_buf(&mut Cursor::new(&bytes[..]), true, |_| {
+        Ok((Vec::new(), HashMap::new()))
+    })
+    .map_err(|err| anyhow!("Unable to parse OBJ: {}", err))?;
+
+    for model in models.iter() {
+        let mesh = &model.mesh;
+        let body = part.add_body(&model.name);
+        let position = |i: u32| -> [f32; 3] {
+            let i = i as usize * 3;
+            [
+                mesh.positions[i],
+                mesh.positions[i + 1],
+                mesh.positions[i + 2],
+            ]
+        };
+
+        // tobj only fills in normals for vertices that have a `vn` reference,
+        // so the normals can only be used if every vertex has one.
+        if mesh.normals.len() == mesh.positions.len() {
+            let first_vertex = part.positions.len() as u32;
+            for (position, normal) in mesh
+                .positions
+                .chunks_exact(3)
+                .zip(mesh.normals.chunks_exact(3))
+            {
+                part.add_vertex(
+                    [position[0], position[1], position[2]],
+                    [normal[0], normal[1], normal[2]],
+                );
+            }
+            for face in mesh.indices.chunks_exact(3) {
+                part.add_triangle(
+                    [
+                        first_vertex + face[0],
+                        first_vertex + face[1],
+                        first_vertex + face[2],
+                    ],
+                    body,
+                );
+            }
+        } else {
+            for face in mesh.indices.chunks_exact(3) {
+                let vertices = [position(face[0]), position(face[1]), position(face[2])];
+                part.add_flat_triangle(vertices, face_normal(&vertices), body);
+            }
+        }
+    }
+
+    Ok(part)
+}
+
+/// tobj reads coordinates in single precision, so if a vertex is far enough
+/// from zero to give the part an origin, every vertex is rewritten relative to
+/// it in double precision before tobj sees the file.
+fn relative_to_origin<'a>(part: &mut Part, bytes: &'a [u8]) -> Cow<'a, [u8]> {
+    let lines = || bytes.split_inclusive(|&byte| byte == b'\n');
+    for line in lines() {
+        if let Some((position, _)) = parse_vertex(line) {
+            part.place_origin(position);
+        }
+    }
+    if part.origin == [0.0; 3] {
+        return Cow::Borrowed(bytes);
+    }
+
+    let mut rewritten = Vec::with_capacity(bytes.len());
+    for line in lines() {
+        match parse_vertex(line) {
+            Some((position, rest)) => {
+                let [x, y, z] = part.local_position(position);
+                rewritten.extend_from_slice(format!("v {} {} {}", x, y, z).as_bytes());
+                rewritten.extend_from_slice(rest);
+            }
+            None => rewritten.extend_from_slice(line),
+        }
+    }
+    Cow::Owned(rewritten)
+}
+
+/// Parses the position of a `v x y z` line, and returns it with the rest of
+/// the line, such as a vertex color.
+fn parse_vertex(line: &[u8]) -> Option<([f64; 3], &[u8])> {
+    let line = std::str::from_utf8(line).ok()?;
+    let mut rest = line.trim_start().strip_prefix('v')?;
+    if !rest.starts_with(char::is_whitespace) {
+        return None;
+    }
+    let mut position = [0.0; 3];
+    for coordinate in position.iter_mut() {
+        let start = rest.trim_start();
+        let end = start.find(char::is_whitespace).unwrap_or(start.len());
+        *coordinate = start[..end].parse().ok()?;
+        rest = &start[end..];
+    }
+    Some((position, rest.as_bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quads_are_triangulated_with_flat_normals() {
+        let obj = b"v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\nf 1 2 3 4\n";
+        let part = load(&Input::from_test_bytes(obj)).unwrap();
+        assert_eq!(part.triangle_count(), 2);
+        for normal in part.normals.iter() {
+            assert_eq!(*normal, [0.0, 0.0, 1.0]);
+        }
+    }
+}
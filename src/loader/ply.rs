@@ -0,0 +1,436 @@
+use super::Input;
+use crate::part::color::srgb_to_linear;
+use crate::part::{face_normal, Part};
+use anyhow::{bail, ensure, Context, Result};
+use std::io::{BufRead, Cursor, Read};
+
+/// The binary layout of a scalar PLY property.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum ScalarType {
+    Char,
+    UChar,
+    Short,
+    UShort,
+    Int,
+    UInt,
+    Float,
+    Double,
+}
+
+impl ScalarType {
+    fn parse(name: &str) -> Result<Self> {
+        Ok(match name {
+            "char" | "int8" => ScalarType::Char,
+            "uchar" | "uint8" => ScalarType::UChar,
+            "short" | "int16" => ScalarType::Short,
+            "ushort" | "uint16" => ScalarType::UShort,
+            "int" | "int32" => ScalarType::Int,
+            "uint" | "uint32" => ScalarType::UInt,
+            "float" | "float32" => ScalarType::Float,
+            "double" | "float64" => ScalarType::Double,
+            _ => bail!("Unsupported PLY property type: {}", name),
+        })
+    }
+
+    /// Returns the value that represents full intensity when this type is
+    /// used for a color channel.
+    fn color_scale(&self) -> f64 {
+        match self {
+            ScalarType::Float | ScalarType::Double => 1.0,
+            ScalarType::UShort => 65535.0,
+            _ => 255.0,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+enum PropertyType {
+    Scalar(ScalarType),
+    List { count: ScalarType, item: ScalarType },
+}
+
+#[derive(Debug)]
+struct Property {
+    name: String,
+    ty: PropertyType,
+}
+
+#[derive(Debug)]
+struct Element {
+    name: String,
+    count: usize,
+    properties: Vec<Property>,
+}
+
+impl Element {
+    fn property_index(&self, name: &str) -> Option<usize> {
+        self.properties.iter().position(|p| p.name == name)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Encoding {
+    Ascii,
+    BinaryLittleEndian,
+    BinaryBigEndian,
+}
+
+struct Header {
+    encoding: Encoding,
+    elements: Vec<Element>,
+}
+
+/// A source of property values from the body of a PLY file.
+trait ValueReader {
+    fn read(&mut self, ty: ScalarType) -> Result<f64>;
+}
+
+struct AsciiValues<'a> {
+    tokens: std::str::SplitWhitespace<'a>,
+}
+
+impl<'a> ValueReader for AsciiValues<'a> {
+    fn read(&mut self, _ty: ScalarType) -> Result<f64> {
+        let token = self
+            .tokens
+            .next()
+            .context("PLY file ended before all elements were read")?;
+        token
+            .parse::<f64>()
+            .with_context(|| format!("Invalid PLY value: {}", token))
+    }
+}
+
+struct BinaryValues<R: Read> {
+    reader: R,
+    big_endian: bool,
+}
+
+impl<R: Read> BinaryValues<R> {
+    fn read_bytes<const N: usize>(&mut self) -> Result<[u8; N]> {
+        let mut bytes = [0u8; N];
+        self.reader
+            .read_exact(&mut bytes)
+            .context("PLY file ended before all elements were read")?;
+        if self.big_endian {
+            bytes.reverse();
+        }
+        Ok(bytes)
+    }
+}
+
+impl<R: Read> ValueReader for BinaryValues<R> {
+    fn read(&mut self, ty: ScalarType) -> Result<f64> {
+        // The bytes are always returned in little endian order.
+        Ok(match ty {
+            ScalarType::Char => i8::from_le_bytes(self.read_bytes()?) as f64,
+            ScalarType::UChar => u8::from_le_bytes(self.read_bytes()?) as f64,
+            ScalarType::Short => i16::from_le_bytes(self.read_bytes()?) as f64,
+            ScalarType::UShort => u16::from_le_bytes(self.read_bytes()?) as f64,
+            ScalarType::Int => i32::from_le_bytes(self.read_bytes()?) as f64,
+            ScalarType::UInt => u32::from_le_bytes(self.read_bytes()?) as f64,
+            ScalarType::Float => f32::from_le_bytes(self.read_bytes()?) as f64,
+            ScalarType::Double => f64::from_le_bytes(self.read_bytes()?),
+        })
+    }
+}
+
+/// Load an ASCII or binary PLY file as a part with a single body. Faces with
+/// more than three vertices are triangulated, and vertex colors are kept when
+/// the file has them.
+pub fn load(input: &Input) -> Result<Part> {
+    let mut reader = Cursor::new(&input.bytes[..]);
+    let header = read_header(&mut reader).context("Invalid PLY header")?;
+
+    match header.encoding {
+        Encoding::Ascii => {
+            let mut body = String::new();
+            reader.read_to_string(&mut body)?;
+            let mut values = AsciiValues {
+                tokens: body.split_whitespace(),
+            };
+            read_body(&header, &mut values)
+        }
+        Encoding::BinaryLittleEndian | Encoding::BinaryBigEndian => {
+            let mut values = BinaryValues {
+                reader,
+                big_endian: header.encoding == Encoding::BinaryBigEndian,
+            };
+            read_body(&header, &mut values)
+        }
+    }
+}
+
+fn read_header<R: BufRead>(reader: &mut R) -> Result<Header> {
+    let mut lines = reader.lines();
+    let mut next_line = || -> Result<String> {
+        Ok(lines
+            .next()
+            .context("PLY header has no end_header line")??)
+    };
+
+    ensure!(
+        next_line()?.trim() == "ply",
+        "File does not start with `ply`"
+    );
+
+    let mut encoding = None;
+    let mut elements: Vec<Element> = Vec::new();
+    loop {
+        let line = next_line()?;
+        let words = line.split_whitespace().collect::<Vec<_>>();
+        match words.as_slice() {
+            ["end_header"] => break,
+            [] | ["comment", ..] | ["obj_info", ..] => {}
+            ["format", format, _version] => {
+                encoding = Some(match *format {
+                    "ascii" => Encoding::Ascii,
+                    "binary_little_endian" => Encoding::BinaryLittleEndian,
+                    "binary_big_endian" => Encoding::BinaryBigEndian,
+                    _ => bail!("Unsupported PLY format: {}", format),
+                });
+            }
+            ["element", name, count] => elements.push(Element {
+                name: name.to_string(),
+                count: count
+                    .parse()
+                    .with_context(|| format!("Invalid element count: {}", count))?,
+                properties: Vec::new(),
+            }),
+            ["property", "list", count, item, name] => elements
+                .last_mut()
+                .context("PLY property declared before any element")?
+                .properties
+                .push(Property {
+                    name: name.to_string(),
+                    ty: PropertyType::List {
+                        count: ScalarType::parse(count)?,
+                        item: ScalarType::parse(item)?,
+                    },
+                }),
+            ["property", ty, name] => elements
+                .last_mut()
+                .context("PLY property declared before any element")?
+                .properties
+                .push(Property {
+                    name: name.to_string(),
+                    ty: PropertyType::Scalar(ScalarType::parse(ty)?),
+                }),
+            _ => bail!("Unexpected PLY header line: {}", line),
+        }
+    }
+
+    Ok(Header {
+        encoding: encoding.context("PLY header has no format line")?,
+        elements,
+    })
+}
+
+/// The values of one element, with each list property flattened to its items.
+fn read_element<V: ValueReader>(element: &Element, values: &mut V) -> Result<Vec<Vec<f64>>> {
+    element
+        .properties
+        .iter()
+        .map(|property| match property.ty {
+            PropertyType::Scalar(ty) => Ok(vec![values.read(ty)?]),
+            PropertyType::List { count, item } => {
+                let count = values.read(count)? as usize;
+                (0..count).map(|_| values.read(item)).collect()
+            }
+        })
+        .collect()
+}
+
+fn read_body<V: ValueReader>(header: &Header, values: &mut V) -> Result<Part> {
+    let mut positions = Vec::new();
+    let mut normals = Vec::new();
+    let mut colors = Vec::new();
+    let mut faces = Vec::new();
+
+    for element in header.elements.iter() {
+        match element.name.as_str() {
+            "vertex" => {
+                let index = |name: &str| -> Result<usize> {
+                    element
+                        .property_index(name)
+                        .with_context(|| format!("PLY vertex has no `{}` property", name))
+                };
+                let xyz = [index("x")?, index("y")?, index("z")?];
+                let normal_xyz = ["nx", "ny", "nz"]
+                    .iter()
+                    .map(|name| element.property_index(name))
+                    .collect::<Option<Vec<_>>>();
+                let rgb = ["red", "green", "blue"]
+                    .iter()
+                    .map(|name| element.property_index(name))
+                    .collect::<Option<Vec<_>>>();
+
+                for _ in 0..element.count {
+                    let vertex = read_element(element, values)?;
+                    let get = |i: usize| vertex[i][0];
+                    positions.push([get(xyz[0]), get(xyz[1]), get(xyz[2])]);
+                    if let Some(normal_xyz) = &normal_xyz {
+                        normals.push([
+                            get(normal_xyz[0]) as f32,
+                            get(normal_xyz[1]) as f32,
+                            get(normal_xyz[2]) as f32,
+                        ]);
+                    }
+                    if let Some(rgb) = &rgb {
+                        let mut color = [0.0; 3];
+                        for (channel, &i) in color.iter_mut().zip(rgb.iter()) {
+                            let scale = match element.properties[i].ty {
+                                PropertyType::Scalar(ty) => ty.color_scale(),
+                                PropertyType::List { .. } => bail!("PLY color is a list"),
+                            };
+                            *channel = srgb_to_linear((get(i) / scale) as f32);
+                        }
+                        colors.push(color);
+                    }
+                }
+            }
+            "face" => {
+                let index = element
+                    .property_index("vertex_indices")
+                    .or_else(|| element.property_index("vertex_index"))
+                    .context("PLY face has no `vertex_indices` property")?;
+                for _ in 0..element.count {
+                    let face = read_element(element, values)?;
+                    faces.push(face[index].iter().map(|&i| i as u32).collect::<Vec<_>>());
+                }
+            }
+            _ => {
+                for _ in 0..element.count {
+                    read_element(element, values)?;
+                }
+            }
+        }
+    }
+
+    for face in faces.iter() {
+        for &i in face.iter() {
+            ensure!(
+                (i as usize) < positions.len(),
+                "PLY face refers to missing vertex {}",
+                i
+            );
+        }
+    }
+
+    let mut part = Part::new();
+    for &position in positions.iter() {
+        part.place_origin(position);
+    }
+    let positions = positions
+        .into_iter()
+        .map(|position| part.local_position(position))
+        .collect::<Vec<_>>();
+    let body = part.add_body("Mesh");
+    let add_vertex = |part: &mut Part, i: usize, normal: [f32; 3]| -> u32 {
+        if colors.is_empty() {
+            part.add_vertex(positions[i], normal)
+        } else {
+            part.add_colored_vertex(positions[i], normal, colors[i])
+        }
+    };
+
+    // Without vertex normals each face gets its own vertices so that it can be
+    // shaded flat.
+    if normals.is_empty() {
+        for face in faces.iter() {
+            for i in 1..face.len().saturating_sub(1) {
+                let corners = [face[0] as usize, face[i] as usize, face[i + 1] as usize];
+                let normal = face_normal(&[
+                    positions[corners[0]],
+                    positions[corners[1]],
+                    positions[corners[2]],
+                ]);
+                let a = add_vertex(&mut part, corners[0], normal);
+                let b = add_vertex(&mut part, corners[1], normal);
+                let c = add_vertex(&mut part, corners[2], normal);
+                part.add_triangle([a, b, c], body);
+            }
+        }
+    } else {
+        for (i, &normal) in normals.iter().enumerate() {
+            add_vertex(&mut part, i, normal);
+        }
+        for face in faces.iter() {
+            for i in 1..face.len().saturating_sub(1) {
+                part.add_triangle([face[0], face[i], face[i + 1]], body);
+            }
+        }
+    }
+
+    Ok(part)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HEADER: &str = "element vertex 3\nproperty float x\nproperty float y\nproperty float z\n\
+                          property uchar red\nproperty uchar green\nproperty uchar blue\n\
+                          element face 1\nproperty list uchar int vertex_indices\nend_header\n";
+
+    fn load_bytes(bytes: &[u8]) -> Part {
+        load(&Input::from_test_bytes(bytes)).unwrap()
+    }
+
+    fn binary(big_endian: bool) -> Vec<u8> {
+        let format = if big_endian {
+            "binary_big_endian"
+        } else {
+            "binary_little_endian"
+        };
+        let mut bytes = format!("ply\nformat {} 1.0\n{}", format, HEADER).into_bytes();
+        for position in &[[0f32, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]] {
+            for coordinate in position {
+                if big_endian {
+                    bytes.extend_from_slice(&coordinate.to_be_bytes());
+                } else {
+                    bytes.extend_from_slice(&coordinate.to_le_bytes());
+                }
+            }
+            bytes.extend_from_slice(&[255, 0, 0]);
+        }
+        bytes.push(3);
+        for index in 0..3i32 {
+            if big_endian {
+                bytes.extend_from_slice(&index.to_be_bytes());
+            } else {
+                bytes.extend_from_slice(&index.to_le_bytes());
+            }
+        }
+        bytes
+    }
+
+    fn assert_red_triangle(part: &Part) {
+        assert_eq!(part.triangle_count(), 1);
+        let triangle = part.triangles().next().unwrap();
+        assert_eq!(
+            part.triangle_positions(triangle),
+            [[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]]
+        );
+        assert_eq!(part.colors.as_ref().unwrap()[0], [1.0, 0.0, 0.0]);
+    }
+
+    #[test]
+    fn ascii_vertices_and_colors() {
+        let text = format!(
+            "ply\nformat ascii 1.0\n{}0 0 0 255 0 0\n1 0 0 255 0 0\n0 1 0 255 0 0\n3 0 1 2\n",
+            HEADER
+        );
+        assert_red_triangle(&load_bytes(text.as_bytes()));
+    }
+
+    #[test]
+    fn binary_little_endian() {
+        assert_red_triangle(&load_bytes(&binary(false)));
+    }
+
+    #[test]
+    fn binary_big_endian() {
+        assert_red_triangle(&load_bytes(&binary(true)));
+    }
+}
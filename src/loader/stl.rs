@@ -0,0 +1,589 @@
+use super::detect::{BINARY_STL_HEADER_SIZE, BINARY_STL_TRIANGLE_SIZE};
+use super::Input;
+use crate::part::color::{palette_color, srgb_to_linear};
+use crate::part::Part;
+use anyhow::{bail, ensure, Context, Result};
+use cgmath::{InnerSpace, Vector3};
+use std::collections::{HashMap, HashSet};
+use std::convert::TryInto;
+
+/// Marks the default color in the header of an STL written by Materialise
+/// Magics. It's followed by red, green, blue and alpha bytes.
+const MAGICS_COLOR_KEY: &[u8] = b"COLOR=";
+
+/// The two conventions for storing RGB555 facet colors in the attribute bytes
+/// of a binary STL.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum ColorConvention {
+    /// VisCAM and SolidView set bit 15 when the facet has a color, which is
+    /// stored blue in the low bits and red in the high bits.
+    VisCam,
+    /// Magics clears bit 15 when the facet has its own color, which is stored
+    /// red in the low bits and blue in the high bits. Facets with bit 15 set
+    /// use the default color from the header.
+    Magics { default: [f32; 3] },
+}
+
+impl ColorConvention {
+    /// Magics files are recognized by the `COLOR=` key in their header, and
+    /// any other file is assumed to follow VisCAM.
+    fn from_header(header: &[u8]) -> Self {
+        let start = header
+            .windows(MAGICS_COLOR_KEY.len())
+            .position(|window| window == MAGICS_COLOR_KEY)
+            .map(|i| i + MAGICS_COLOR_KEY.len());
+        match start.and_then(|start| header.get(start..start + 3)) {
+            Some(rgb) => ColorConvention::Magics {
+                default: [
+                    srgb_to_linear(rgb[0] as f32 / 255.0),
+                    srgb_to_linear(rgb[1] as f32 / 255.0),
+                    srgb_to_linear(rgb[2] as f32 / 255.0),
+                ],
+            },
+            None => ColorConvention::VisCam,
+        }
+    }
+
+    /// Returns the color stored in the attribute bytes of a facet, or `None`
+    /// if the facet has no color.
+    fn facet_color(&self, attribute: u16) -> Option<[f32; 3]> {
+        let valid = attribute & 0x8000 != 0;
+        let channel = |shift: u16| srgb_to_linear(((attribute >> shift) & 0x1f) as f32 / 31.0);
+        match self {
+            ColorConvention::VisCam if valid => Some([channel(10), channel(5), channel(0)]),
+            ColorConvention::VisCam => None,
+            ColorConvention::Magics { default } if valid => Some(*default),
+            ColorConvention::Magics { .. } => Some([channel(0), channel(5), channel(10)]),
+        }
+    }
+}
+
+/// Binary STLs with more than this many times the triangle budget are
+/// simplified by vertex clustering as they're read, which leaves about this
+/// many times the budget for the slower, more careful decimation to finish.
+const CLUSTERING_FACTOR: usize = 4;
+
+/// Cells are numbered from the low corner of the bounds with this many bits
+/// for each axis, so that a cell fits in a single `u64` key.
+const CELL_BITS: u32 = 21;
+
+/// Load a binary STL as a part with a single body. Facets are colored from
+/// their attribute bytes if the file uses either of the RGB555 conventions.
+///
+/// When a triangle budget is given and the file holds many times more
+/// triangles than it, the facets are clustered as they're read from the file,
+/// so that a huge scan never has to be held in memory at full detail.
+pub fn load_binary(input: &Input, max_triangles: Option<usize>) -> Result<Part> {
+    let bytes = &input.bytes;
+    ensure!(
+        bytes.len() >= BINARY_STL_HEADER_SIZE,
+        "File is too short to have a binary STL header"
+    );
+    let count = u32::from_le_bytes(bytes[80..84].try_into().unwrap()) as usize;
+    let available = (bytes.len() - BINARY_STL_HEADER_SIZE) / BINARY_STL_TRIANGLE_SIZE;
+    ensure!(
+        count <= available,
+        "Header declares {} triangles but the file only holds {}",
+        count,
+        available
+    );
+    let records = || {
+        bytes[BINARY_STL_HEADER_SIZE..]
+            .chunks_exact(BINARY_STL_TRIANGLE_SIZE)
+            .take(count)
+            .map(read_record)
+    };
+
+    let convention = ColorConvention::from_header(&bytes[..80]);
+    // Most exporters leave the attribute bytes zeroed, which the Magics
+    // convention would read as black, so a Magics file also needs at least
+    // one facet with a color of its own before colors are used at all. The
+    // same pass finds the bounds and area that clustering is planned from.
+    let mut summary = Summary::new();
+    let mut colored = false;
+    for (facet, attribute) in records() {
+        summary.add(&facet);
+        colored |= match convention {
+            ColorConvention::VisCam => attribute & 0x8000 != 0,
+            ColorConvention::Magics { .. } => attribute != 0,
+        };
+    }
+    if colored {
+        let name = match convention {
+            ColorConvention::VisCam => "VisCAM",
+            ColorConvention::Magics { .. } => "Magics",
+        };
+        log::info!("Using {} facet colors", name);
+    }
+    let color = |attribute: u16| convention.facet_color(attribute).filter(|_| colored);
+
+    if let Some(max_triangles) = max_triangles.filter(|&max| count > CLUSTERING_FACTOR * max) {
+        if let Some(cell_size) = summary.cell_size(CLUSTERING_FACTOR * max_triangles / 2) {
+            let mut clusters = Clusters::new(summary.min, cell_size);
+            for (facet, attribute) in records() {
+                // The attribute stands in for the color, so that vertices on
+                // either side of a color seam stay apart.
+                let attribute = if colored { attribute } else { 0 };
+                clusters.add(&facet, attribute, color(attribute));
+            }
+            log::info!(
+                "Clustered {} triangles into {} while reading",
+                count,
+                clusters.triangles.len()
+            );
+            return Ok(clusters.into_part(colored));
+        }
+    }
+
+    let mut part = Part::new();
+    if colored {
+        part.colors = Some(Vec::new());
+    }
+    part.reserve(count * 3, count);
+    let body = part.add_body("Mesh");
+    for (facet, attribute) in records() {
+        // The winding is trusted over the stored normal, which exporters
+        // often leave zeroed. `Part::orient_outward` can repair the winding.
+        match color(attribute) {
+            Some(color) => {
+                part.add_colored_flat_triangle(facet.vertices, facet.normal, color, body)
+            }
+            None => part.add_flat_triangle(facet.vertices, facet.normal, body),
+        }
+    }
+
+    Ok(part)
+}
+
+/// Reads the facet and attribute bytes of a binary STL record.
+fn read_record(record: &[u8]) -> (Facet, u16) {
+    let vector = |offset: usize| -> [f32; 3] {
+        let f = |i: usize| {
+            let start = offset + i * 4;
+            f32::from_le_bytes(record[start..start + 4].try_into().unwrap())
+        };
+        [f(0), f(1), f(2)]
+    };
+    let facet = Facet {
+        normal: vector(0),
+        vertices: [vector(12), vector(24), vector(36)],
+    };
+    let attribute = u16::from_le_bytes(record[48..50].try_into().unwrap());
+    (facet, attribute)
+}
+
+/// The bounds and surface area of the facets of a binary STL, gathered in a
+/// single pass over the file. Facets with a NaN or infinite coordinate are
+/// left out.
+struct Summary {
+    min: [f32; 3],
+    max: [f32; 3],
+    area: f64,
+}
+
+impl Summary {
+    fn new() -> Self {
+        Self {
+            min: [f32::INFINITY; 3],
+            max: [f32::NEG_INFINITY; 3],
+            area: 0.0,
+        }
+    }
+
+    fn add(&mut self, facet: &Facet) {
+        if !is_finite(facet) {
+            return;
+        }
+        for vertex in facet.vertices.iter() {
+            for ((min, max), &coordinate) in
+                self.min.iter_mut().zip(self.max.iter_mut()).zip(vertex)
+            {
+                *min = min.min(coordinate);
+                *max = max.max(coordinate);
+            }
+        }
+        let [a, b, c] = facet.vertices;
+        let (a, b, c) = (Vector3::from(a), Vector3::from(b), Vector3::from(c));
+        self.area += (b - a).cross(c - a).magnitude() as f64 / 2.0;
+    }
+
+    /// Returns the size of the cells that would cluster the surface into
+    /// about the given number of vertices, or `None` if the facets have no
+    /// area to cluster.
+    fn cell_size(&self, vertices: usize) -> Option<f32> {
+        let largest_extent = (0..3)
+            .map(|axis| self.max[axis] - self.min[axis])
+            .fold(0.0, f32::max);
+        // A surface crosses about its area over the area of a face of a cell.
+        let cell_size = (self.area / vertices.max(1) as f64).sqrt() as f32;
+        let cell_size = cell_size.max(largest_extent / ((1 << CELL_BITS) - 1) as f32);
+        if cell_size.is_finite() && cell_size > 0.0 {
+            Some(cell_size)
+        } else {
+            None
+        }
+    }
+}
+
+/// Facets merged by vertex clustering: every corner that falls in the same
+/// cell of a grid, and has the same color, becomes a single vertex at the
+/// average of their positions. Facets that lose a corner this way disappear.
+struct Clusters {
+    origin: [f32; 3],
+    cell_size: f32,
+    /// Index into `vertices` of the vertex for each occupied cell and color.
+    cells: HashMap<(u64, u16), u32>,
+    vertices: Vec<Cluster>,
+    triangles: Vec<[u32; 3]>,
+    /// The triangles already added, each starting at its lowest index, so
+    /// that facets which cluster to the same triangle only add it once.
+    seen: HashSet<[u32; 3]>,
+}
+
+struct Cluster {
+    position_sum: [f64; 3],
+    normal_sum: [f32; 3],
+    count: u32,
+    color: Option<[f32; 3]>,
+}
+
+impl Clusters {
+    fn new(origin: [f32; 3], cell_size: f32) -> Self {
+        Self {
+            origin,
+            cell_size,
+            cells: HashMap::new(),
+            vertices: Vec::new(),
+            triangles: Vec::new(),
+            seen: HashSet::new(),
+        }
+    }
+
+    fn add(&mut self, facet: &Facet, attribute: u16, color: Option<[f32; 3]>) {
+        if !is_finite(facet) {
+            return;
+        }
+        let mut triangle = [0; 3];
+        for (index, vertex) in triangle.iter_mut().zip(facet.vertices.iter()) {
+            *index = self.add_corner(*vertex, facet.normal, attribute, color);
+        }
+        let [a, b, c] = triangle;
+        if a == b || b == c || c == a {
+            return;
+        }
+        let lowest = (0..3).min_by_key(|&i| triangle[i]).unwrap();
+        triangle.rotate_left(lowest);
+        if self.seen.insert(triangle) {
+            self.triangles.push(triangle);
+        }
+    }
+
+    fn add_corner(
+        &mut self,
+        position: [f32; 3],
+        normal: [f32; 3],
+        attribute: u16,
+        color: Option<[f32; 3]>,
+    ) -> u32 {
+        let mut key = 0;
+        for (&coordinate, &origin) in position.iter().zip(self.origin.iter()) {
+            let cell = ((coordinate - origin) / self.cell_size) as u64;
+            key = key << CELL_BITS | cell.min((1 << CELL_BITS) - 1);
+        }
+        let vertices = &mut self.vertices;
+        let index = *self.cells.entry((key, attribute)).or_insert_with(|| {
+            vertices.push(Cluster {
+                position_sum: [0.0; 3],
+                normal_sum: [0.0; 3],
+                count: 0,
+                color,
+            });
+            vertices.len() as u32 - 1
+        });
+
+        let cluster = &mut self.vertices[index as usize];
+        for axis in 0..3 {
+            cluster.position_sum[axis] += position[axis] as f64;
+            cluster.normal_sum[axis] += normal[axis];
+        }
+        cluster.count += 1;
+        index
+    }
+
+    /// Builds a part with a single body from the clustered triangles. Each
+    /// vertex keeps the sum of the stored normals around it, which is enough
+    /// to tell whether the facets' windings agree with them.
+    fn into_part(self, colored: bool) -> Part {
+        let mut part = Part::new();
+        if colored {
+            part.colors = Some(Vec::new());
+        }
+        part.reserve(self.vertices.len(), self.triangles.len());
+        let body = part.add_body("Mesh");
+        for cluster in self.vertices.iter() {
+            let count = cluster.count as f64;
+            let position = [
+                (cluster.position_sum[0] / count) as f32,
+                (cluster.position_sum[1] / count) as f32,
+                (cluster.position_sum[2] / count) as f32,
+            ];
+            match cluster.color {
+                Some(color) => part.add_colored_vertex(position, cluster.normal_sum, color),
+                None => part.add_vertex(position, cluster.normal_sum),
+            };
+        }
+        for triangle in self.triangles {
+            part.add_triangle(triangle, body);
+        }
+        part
+    }
+}
+
+fn is_finite(facet: &Facet) -> bool {
+    facet
+        .vertices
+        .iter()
+        .flatten()
+        .all(|coordinate| coordinate.is_finite())
+}
+
+struct Facet {
+    normal: [f32; 3],
+    vertices: [[f32; 3]; 3],
+}
+
+/// Load an ASCII STL as a part with a body for each `solid name ... endsolid`
+/// block in the file. When there's more than one solid, each is colored from
+/// the palette so that they can be told apart.
+pub fn load_ascii(input: &Input) -> Result<Part> {
+    let mut part = Part::new();
+    let mut in_solid = false;
+    // The normal and vertices of the facet being read, if any.
+    let mut facet: Option<([f64; 3], Vec<[f64; 3]>)> = None;
+
+    // Lines are decoded one at a time, so that a stray byte that isn't UTF-8,
+    // as in a solid name written in another encoding, doesn't make a copy of
+    // the whole file.
+    for (i, line) in input.bytes.split(|&byte| byte == b'\n').enumerate() {
+        let line_number = i + 1;
+        let line = String::from_utf8_lossy(line);
+        let line = line.trim().trim_start_matches('\u{feff}');
+        let (keyword, rest) = match line.find(char::is_whitespace) {
+            Some(end) => (&line[..end], line[end..].trim()),
+            None => (line, ""),
+        };
+
+        match keyword {
+            "" | "outer" | "endloop" => {}
+            "solid" => {
+                ensure!(
+                    !in_solid,
+                    "Line {}: Solid starts inside another solid",
+                    line_number
+                );
+                in_solid = true;
+                let name = if rest.is_empty() {
+                    format!("Solid {}", part.bodies.len())
+                } else {
+                    rest.to_string()
+                };
+                part.add_body(&name);
+            }
+            "endsolid" => {
+                ensure!(in_solid, "Line {}: Unexpected endsolid", line_number);
+                in_solid = false;
+            }
+            "facet" => {
+                ensure!(in_solid, "Line {}: Facet outside of a solid", line_number);
+                let normal = rest
+                    .strip_prefix("normal")
+                    .with_context(|| format!("Line {}: Facet has no normal", line_number))?;
+                facet = Some((parse_vector(normal, line_number)?, Vec::new()));
+            }
+            "vertex" => facet
+                .as_mut()
+                .with_context(|| format!("Line {}: Vertex outside of a facet", line_number))?
+                .1
+                .push(parse_vector(rest, line_number)?),
+            "endfacet" => {
+                let (normal, vertices) = facet
+                    .take()
+                    .with_context(|| format!("Line {}: Unexpected endfacet", line_number))?;
+                ensure!(
+                    vertices.len() >= 3,
+                    "Line {}: Facet has fewer than three vertices",
+                    line_number
+                );
+                // Some exporters write polygons, which are split into a fan of
+                // triangles.
+                let body = part.bodies.len() as u32 - 1;
+                let normal = [normal[0] as f32, normal[1] as f32, normal[2] as f32];
+                for &vertex in vertices.iter() {
+                    part.place_origin(vertex);
+                }
+                let vertices = vertices
+                    .into_iter()
+                    .map(|vertex| part.local_position(vertex))
+                    .collect::<Vec<_>>();
+                for j in 1..vertices.len() - 1 {
+                    part.add_flat_triangle(
+                        [vertices[0], vertices[j], vertices[j + 1]],
+                        normal,
+                        body,
+                    );
+                }
+            }
+            _ => bail!("Line {}: Unexpected `{}`", line_number, keyword),
+        }
+    }
+    // A missing endsolid at the end of the file is tolerated, since the
+    // facets are all there.
+    ensure!(!part.bodies.is_empty(), "STL has no solids");
+
+    // Every facet has its own vertices, so each vertex takes the color of the
+    // solid its facet is in.
+    if part.bodies.len() > 1 {
+        let colors = part
+            .triangle_bodies
+            .iter()
+            .flat_map(|&body| [palette_color(body as usize); 3])
+            .collect();
+        part.colors = Some(colors);
+    }
+
+    Ok(part)
+}
+
+/// Parses three numbers in double precision, so that a part modelled far from
+/// the origin can be moved near it before it's narrowed to single precision.
+fn parse_vector(text: &str, line_number: usize) -> Result<[f64; 3]> {
+    let values = text
+        .split_whitespace()
+        .map(|value| value.parse::<f64>())
+        .collect::<std::result::Result<Vec<_>, _>>()
+        .ok()
+        .filter(|values| values.len() == 3)
+        .with_context(|| format!("Line {}: Expected three numbers", line_number))?;
+    Ok([values[0], values[1], values[2]])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Builds a binary STL with the given facets and attribute bytes.
+    fn binary_stl_facets(header: &[u8], facets: &[([[f32; 3]; 3], u16)]) -> Input {
+        let mut bytes = header.to_vec();
+        bytes.resize(80, 0);
+        bytes.extend_from_slice(&(facets.len() as u32).to_le_bytes());
+        for (vertices, attribute) in facets {
+            let normal = [0.0f32, 0.0, 1.0];
+            for coordinate in normal.iter().chain(vertices.iter().flatten()) {
+                bytes.extend_from_slice(&coordinate.to_le_bytes());
+            }
+            bytes.extend_from_slice(&attribute.to_le_bytes());
+        }
+        Input::from_test_bytes(&bytes)
+    }
+
+    /// Builds a binary STL with the same triangle for each attribute.
+    fn binary_stl(header: &[u8], attributes: &[u16]) -> Input {
+        let vertices = [[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]];
+        let facets = attributes
+            .iter()
+            .map(|&attribute| (vertices, attribute))
+            .collect::<Vec<_>>();
+        binary_stl_facets(header, &facets)
+    }
+
+    fn triangle_colors(part: &Part) -> Vec<[f32; 3]> {
+        let colors = part.colors.as_ref().unwrap();
+        part.triangles()
+            .map(|triangle| colors[triangle[0] as usize])
+            .collect()
+    }
+
+    #[test]
+    fn viscam_colors_are_blue_in_the_low_bits() {
+        let part = load_binary(
+            &binary_stl(b"", &[0x8000 | (0x1f << 10), 0x8000 | 0x1f]),
+            None,
+        )
+        .unwrap();
+        assert_eq!(triangle_colors(&part), [[1.0, 0.0, 0.0], [0.0, 0.0, 1.0]]);
+    }
+
+    #[test]
+    fn magics_colors_are_red_in_the_low_bits_and_default_from_the_header() {
+        let header = b"COLOR=\x00\xff\x00\xff";
+        let part = load_binary(&binary_stl(header, &[0x1f, 0x8000]), None).unwrap();
+        assert_eq!(triangle_colors(&part), [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0]]);
+    }
+
+    #[test]
+    fn zeroed_attributes_are_uncolored() {
+        let part = load_binary(&binary_stl(b"COLOR=\x00\xff\x00\xff", &[0, 0]), None).unwrap();
+        assert_eq!(part.triangle_count(), 2);
+        assert!(part.colors.is_none());
+    }
+
+    #[test]
+    fn huge_binary_stls_are_clustered_while_reading() {
+        // A flat square 64 units across, made of unit squares.
+        let mut facets = Vec::new();
+        for y in 0..64 {
+            for x in 0..64 {
+                let (x, y) = (x as f32, y as f32);
+                let corners = [
+                    [x, y, 0.0],
+                    [x + 1.0, y, 0.0],
+                    [x + 1.0, y + 1.0, 0.0],
+                    [x, y + 1.0, 0.0],
+                ];
+                facets.push(([corners[0], corners[1], corners[2]], 0));
+                facets.push(([corners[0], corners[2], corners[3]], 0));
+            }
+        }
+        let input = binary_stl_facets(b"", &facets);
+
+        let part = load_binary(&input, Some(8192)).unwrap();
+        assert_eq!(part.triangle_count(), 8192);
+
+        let part = load_binary(&input, Some(100)).unwrap();
+        assert!(part.triangle_count() < 8192 / 4);
+        assert!(part.triangle_count() > 100);
+        for position in part.positions.iter() {
+            assert!(position
+                .iter()
+                .all(|&coordinate| (0.0..=64.0).contains(&coordinate)));
+            assert_eq!(position[2], 0.0);
+        }
+    }
+
+    #[test]
+    fn ascii_solids_are_separate_bodies_with_palette_colors() {
+        let text = b"solid first\n\
+              facet normal 0 0 1\n\
+                outer loop\n\
+                  vertex 0 0 0\n\
+                  vertex 1 0 0\n\
+                  vertex 0 1 0\n\
+                endloop\n\
+              endfacet\n\
+            endsolid first\n\
+            solid\n\
+              facet normal 0 0 1\n\
+                outer loop\n\
+                  vertex 0 0 1\n\
+                  vertex 1 0 1\n\
+                  vertex 0 1 1\n\
+                endloop\n\
+              endfacet\n\
+            endsolid\n";
+        let part = load_ascii(&Input::from_test_bytes(text)).unwrap();
+        let names = part.bodies.iter().map(|body| body.name.as_str());
+        assert_eq!(names.collect::<Vec<_>>(), ["first", "Solid 1"]);
+        assert_eq!(part.triangle_bodies, [0, 1]);
+        assert_eq!(triangle_colors(&part), [palette_color(0), palette_color(1)]);
+    }
+}
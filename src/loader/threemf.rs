@@ -0,0 +1,523 @@
+use super::Input;
+use crate::part::color::srgb_to_linear;
+use crate::part::{face_normal, Part, DEFAULT_COLOR};
+use crate::units::Unit;
+use anyhow::{anyhow, ensure, Context, Result};
+use cgmath::{Matrix4, Point3, SquareMatrix, Transform};
+use quick_xml::events::{BytesStart, Event};
+use std::collections::HashMap;
+use std::io::{Cursor, Read, Seek};
+
+/// The relationship type that identifies the 3D model part of a package.
+const MODEL_RELATIONSHIP_TYPE: &str =
+    "http://schemas.microsoft.com/3dmanufacturing/2013/01/3dmodel";
+
+/// Components can refer to other objects, so a malformed file could contain a
+/// cycle. Nesting deeper than this is treated as an error.
+const MAX_COMPONENT_DEPTH: usize = 32;
+
+struct Triangle {
+    vertices: [usize; 3],
+    pid: Option<u32>,
+    p1: Option<usize>,
+}
+
+struct Component {
+    object_id: u32,
+    transform: Matrix4<f64>,
+}
+
+#[derive(Default)]
+struct Object {
+    name: Option<String>,
+    pid: Option<u32>,
+    pindex: Option<usize>,
+    /// Read in double precision, like the transforms, so that a part modelled
+    /// far from the origin keeps its detail until it's moved near it.
+    vertices: Vec<[f64; 3]>,
+    triangles: Vec<Triangle>,
+    components: Vec<Component>,
+}
+
+struct Item {
+    object_id: u32,
+    transform: Matrix4<f64>,
+}
+
+struct Model {
+    unit: Unit,
+    objects: HashMap<u32, Object>,
+    /// Colors of each `<basematerials>` or `<colorgroup>` resource.
+    materials: HashMap<u32, Vec<[f32; 3]>>,
+    items: Vec<Item>,
+}
+
+/// Load the build plate of a 3MF package. Each build item becomes a body of
+/// the part, placed with its transform and colored from its material. The
+/// part has the unit the model declares, which is millimetres by default.
+pub fn load(input: &Input) -> Result<Part> {
+    let mut archive = zip::ZipArchive::new(Cursor::new(&input.bytes[..]))
+        .context("Unable to open 3MF package")?;
+    let model_path = model_path(&mut archive)?;
+
+    let mut xml = String::new();
+    archive
+        .by_name(&model_path)
+        .with_context(|| format!("3MF package has no model at {}", model_path))?
+        .read_to_string(&mut xml)?;
+    let model = parse_model(&xml).context("Invalid 3MF model")?;
+
+    let mut part = Part {
+        unit: Some(model.unit),
+        ..Part::new()
+    };
+    for item in model.items.iter() {
+        let object = model.objects.get(&item.object_id).with_context(|| {
+            format!("3MF build item refers to missing object {}", item.object_id)
+        })?;
+        let name = object
+            .name
+            .clone()
+            .unwrap_or_else(|| format!("Object {}", item.object_id));
+        let body = part.add_body(&name);
+        add_object(&mut part, &model, item.object_id, item.transform, body, 0)?;
+    }
+
+    Ok(part)
+}
+
+/// Returns the name of the model file within the package, as given by the
+/// package relationships.
+fn model_path<R: Read + Seek>(archive: &mut zip::ZipArchive<R>) -> Result<String> {
+    let mut xml = String::new();
+    match archive.by_name("_rels/.rels") {
+        Ok(mut rels) => rels.read_to_string(&mut xml)?,
+        Err(_) => return Ok("3D/3dmodel.model".to_string()),
+    };
+
+    let mut reader = quick_xml::Reader::from_str(&xml);
+    let mut buf = Vec::new();
+    loop {
+        match reader.read_event(&mut buf)? {
+            Event::Start(e) | Event::Empty(e) if e.local_name() == b"Relationship" => {
+                let attributes = attributes(&e)?;
+                if attributes.get("Type").map(String::as_str) == Some(MODEL_RELATIONSHIP_TYPE) {
+                    let target = attributes
+                        .get("Target")
+                        .context("3MF model relationship has no target")?;
+                    return Ok(target.trim_start_matches('/').to_string());
+                }
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+        buf.clear();
+    }
+
+    Ok("3D/3dmodel.model".to_string())
+}
+
+fn parse_model(xml: &str) -> Result<Model> {
+    let mut model = Model {
+        unit: Unit::Millimetre,
+        objects: HashMap::new(),
+        materials: HashMap::new(),
+        items: Vec::new(),
+    };
+    let mut reader = quick_xml::Reader::from_str(xml);
+    let mut buf = Vec::new();
+
+    // The resource that `<vertex>`, `<triangle>`, `<base>` and `<color>`
+    // elements are added to.
+    let mut object: Option<(u32, Object)> = None;
+    let mut material_group: Option<u32> = None;
+
+    loop {
+        let event = reader.read_event(&mut buf)?;
+        match &event {
+            Event::Start(e) | Event::Empty(e) => {
+                let attributes = attributes(e)?;
+                match e.local_name() {
+                    b"model" => {
+                        if let Some(unit) = attributes.get("unit") {
+                            model.unit = parse_unit(unit)?;
+                        }
+                    }
+                    b"object" => {
+                        object = Some((
+                            parse_attribute(&attributes, "id")?,
+                            Object {
+                                name: attributes.get("name").cloned(),
+                                pid: parse_optional_attribute(&attributes, "pid")?,
+                                pindex: parse_optional_attribute(&attributes, "pindex")?,
+                                ..Default::default()
+                            },
+                        ));
+                    }
+                    b"vertex" => {
+                        if let Some((_, object)) = &mut object {
+                            object.vertices.push([
+                                parse_attribute(&attributes, "x")?,
+                                parse_attribute(&attributes, "y")?,
+                                parse_attribute(&attributes, "z")?,
+                            ]);
+                        }
+                    }
+                    b"triangle" => {
+                        if let Some((_, object)) = &mut object {
+                            object.triangles.push(Triangle {
+                                vertices: [
+                                    parse_attribute(&attributes, "v1")?,
+                                    parse_attribute(&attributes, "v2")?,
+                                    parse_attribute(&attributes, "v3")?,
+                                ],
+                                pid: parse_optional_attribute(&attributes, "pid")?,
+                                p1: parse_optional_attribute(&attributes, "p1")?,
+                            });
+                        }
+                    }
+                    b"component" => {
+                        if let Some((_, object)) = &mut object {
+                            object.components.push(Component {
+                                object_id: parse_attribute(&attributes, "objectid")?,
+                                transform: parse_transform(&attributes)?,
+                            });
+                        }
+                    }
+                    b"basematerials" | b"colorgroup" => {
+                        let id = parse_attribute(&attributes, "id")?;
+                        model.materials.insert(id, Vec::new());
+                        material_group = Some(id);
+                    }
+                    b"base" | b"color" => {
+                        if let Some(id) = material_group {
+                            let key = if e.local_name() == b"base" {
+                                "displaycolor"
+                            } else {
+                                "color"
+                            };
+                            let color = attributes
+                                .get(key)
+                                .map(|color| parse_color(color))
+                                .transpose()?
+                                .unwrap_or(DEFAULT_COLOR);
+                            model.materials.get_mut(&id).unwrap().push(color);
+                        }
+                    }
+                    b"item" => model.items.push(Item {
+                        object_id: parse_attribute(&attributes, "objectid")?,
+                        transform: parse_transform(&attributes)?,
+                    }),
+                    _ => {}
+                }
+
+                // Empty elements don't have an end event, so they have to be
+                // closed here.
+                if let Event::Empty(_) = event {
+                    close_element(e.local_name(), &mut model, &mut object, &mut material_group);
+                }
+            }
+            Event::End(e) => {
+                close_element(e.local_name(), &mut model, &mut object, &mut material_group)
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+        buf.clear();
+    }
+
+    Ok(model)
+}
+
+fn close_element(
+    name: &[u8],
+    model: &mut Model,
+    object: &mut Option<(u32, Object)>,
+    material_group: &mut Option<u32>,
+) {
+    match name {
+        b"object" => {
+            if let Some((id, object)) = object.take() {
+                model.objects.insert(id, object);
+            }
+        }
+        b"basematerials" | b"colorgroup" => *material_group = None,
+        _ => {}
+    }
+}
+
+/// Adds the triangles of an object and its components to the given body.
+fn add_object(
+    part: &mut Part,
+    model: &Model,
+    object_id: u32,
+    transform: Matrix4<f64>,
+    body: u32,
+    depth: usize,
+) -> Result<()> {
+    ensure!(
+        depth < MAX_COMPONENT_DEPTH,
+        "3MF components are nested too deeply"
+    );
+    let object = model
+        .objects
+        .get(&object_id)
+        .with_context(|| format!("3MF component refers to missing object {}", object_id))?;
+
+    // A mirroring transform turns counter-clockwise triangles clockwise.
+    let mirrored = transform.determinant() < 0.0;
+
+    for triangle in object.triangles.iter() {
+        let mut positions = [[0.0; 3]; 3];
+        for (position, &i) in positions.iter_mut().zip(triangle.vertices.iter()) {
+            let vertex = object
+                .vertices
+                .get(i)
+                .with_context(|| format!("3MF triangle refers to missing vertex {}", i))?;
+            *position = transform.transform_point(Point3::from(*vertex)).into();
+            part.place_origin(*position);
+        }
+        let mut vertices = [
+            part.local_position(positions[0]),
+            part.local_position(positions[1]),
+            part.local_position(positions[2]),
+        ];
+        if mirrored {
+            vertices.swap(1, 2);
+        }
+
+        let normal = face_normal(&vertices);
+        let color = match (triangle.pid.or(object.pid), triangle.p1.or(object.pindex)) {
+            (Some(pid), Some(index)) => model
+                .materials
+                .get(&pid)
+                .and_then(|colors| colors.get(index))
+                .copied(),
+            _ => None,
+        };
+        let corners = vertices
+            .iter()
+            .map(|&vertex| match color {
+                Some(color) => part.add_colored_vertex(vertex, normal, color),
+                None => part.add_vertex(vertex, normal),
+            })
+            .collect::<Vec<_>>();
+        part.add_triangle([corners[0], corners[1], corners[2]], body);
+    }
+
+    for component in object.components.iter() {
+        add_object(
+            part,
+            model,
+            component.object_id,
+            transform * component.transform,
+            body,
+            depth + 1,
+        )?;
+    }
+
+    Ok(())
+}
+
+fn attributes(element: &BytesStart) -> Result<HashMap<String, String>> {
+    element
+        .attributes()
+        .map(|attribute| {
+            let attribute = attribute?;
+            let key = String::from_utf8(attribute.key.to_vec())?;
+            let value = String::from_utf8(attribute.unescaped_value()?.into_owned())?;
+            Ok((key, value))
+        })
+        .collect()
+}
+
+fn parse_attribute<T: std::str::FromStr>(
+    attributes: &HashMap<String, String>,
+    key: &str,
+) -> Result<T> {
+    parse_optional_attribute(attributes, key)?
+        .with_context(|| format!("Missing `{}` attribute", key))
+}
+
+fn parse_optional_attribute<T: std::str::FromStr>(
+    attributes: &HashMap<String, String>,
+    key: &str,
+) -> Result<Option<T>> {
+    attributes
+        .get(key)
+        .map(|value| {
+            value
+                .parse()
+                .map_err(|_| anyhow!("Invalid `{}` attribute: {}", key, value))
+        })
+        .transpose()
+}
+
+/// Parses the optional `transform` attribute of a build item or component.
+/// 3MF writes the matrix as twelve numbers in row-vector order, which are the
+/// first three rows of each column in cgmath's column-vector convention.
+fn parse_transform(attributes: &HashMap<String, String>) -> Result<Matrix4<f64>> {
+    let transform = match attributes.get("transform") {
+        Some(transform) => transform,
+        None => return Ok(Matrix4::identity()),
+    };
+    let m = transform
+        .split_whitespace()
+        .map(|value| value.parse::<f64>())
+        .collect::<std::result::Result<Vec<_>, _>>()
+        .with_context(|| format!("Invalid transform: {}", transform))?;
+    ensure!(m.len() == 12, "Invalid transform: {}", transform);
+
+    #[rustfmt::skip]
+    let matrix = Matrix4::new(
+        m[0], m[1], m[2], 0.0,
+        m[3], m[4], m[5], 0.0,
+        m[6], m[7], m[8], 0.0,
+        m[9], m[10], m[11], 1.0,
+    );
+    Ok(matrix)
+}
+
+/// Returns the unit with the given name in the 3MF specification.
+fn parse_unit(name: &str) -> Result<Unit> {
+    match name {
+        "micron" => Ok(Unit::Micrometre),
+        "millimeter" => Ok(Unit::Millimetre),
+        "centimeter" => Ok(Unit::Centimetre),
+        "meter" => Ok(Unit::Metre),
+        "inch" => Ok(Unit::Inch),
+        "foot" => Ok(Unit::Foot),
+        _ => Err(anyhow!("Unknown unit {}", name)),
+    }
+}
+
+/// Parses a `#RRGGBB` or `#RRGGBBAA` sRGB color. The alpha channel is ignored.
+fn parse_color(color: &str) -> Result<[f32; 3]> {
+    let hex = color.trim_start_matches('#');
+    ensure!(
+        (hex.len() == 6 || hex.len() == 8) && hex.is_ascii(),
+        "Invalid color: {}",
+        color
+    );
+    let mut rgb = [0.0; 3];
+    for (i, channel) in rgb.iter_mut().enumerate() {
+        let value = u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16)
+            .with_context(|| format!("Invalid color: {}", color))?;
+        *channel = srgb_to_linear(value as f32 / 255.0);
+    }
+    Ok(rgb)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    const MODEL: &str = r##"<?xml version="1.0" encoding="UTF-8"?>
+<model unit="millimeter" xmlns="http://schemas.microsoft.com/3dmanufacturing/core/2015/02">
+  <resources>
+    <basematerials id="1">
+      <base name="Red" displaycolor="#FF0000" />
+    </basematerials>
+    <object id="2" name="Triangle" pid="1" pindex="0">
+      <mesh>
+        <vertices>
+          <vertex x="0" y="0" z="0" />
+          <vertex x="1" y="0" z="0" />
+          <vertex x="0" y="1" z="0" />
+        </vertices>
+        <triangles>
+          <triangle v1="0" v2="1" v3="2" />
+        </triangles>
+      </mesh>
+    </object>
+  </resources>
+  <build>
+    <item objectid="2" />
+    <item objectid="2" transform="-1 0 0 0 1 0 0 0 1 10 0 0" />
+  </build>
+</model>
+"##;
+
+    fn package(model: &str) -> Input {
+        let mut zip = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        zip.start_file("3D/3dmodel.model", zip::write::FileOptions::default())
+            .unwrap();
+        zip.write_all(model.as_bytes()).unwrap();
+        Input::from_test_bytes(&zip.finish().unwrap().into_inner())
+    }
+
+    #[test]
+    fn build_items_are_transformed_and_colored() {
+        let part = load(&package(MODEL)).unwrap();
+        assert_eq!(part.bodies.len(), 2);
+        assert_eq!(part.triangle_count(), 2);
+
+        // The mirrored item keeps its triangle facing outwards.
+        let mirrored = part.triangles().nth(1).unwrap();
+        assert_eq!(
+            part.triangle_positions(mirrored),
+            [[10.0, 0.0, 0.0], [10.0, 1.0, 0.0], [9.0, 0.0, 0.0]]
+        );
+        assert_eq!(part.normals[mirrored[0] as usize], [0.0, 0.0, 1.0]);
+        assert_eq!(
+            part.colors.as_ref().unwrap()[mirrored[0] as usize],
+            [1.0, 0.0, 0.0]
+        );
+    }
+
+    #[test]
+    fn units_and_mirrored_items_keep_the_volume() {
+        // A ten inch cube, and a copy of it mirrored and stretched to twice
+        // its width, which must still enclose a positive volume.
+        let mut vertices = String::new();
+        for i in 0..8 {
+            let corner = |bit: u32| if i & bit == 0 { 0 } else { 10 };
+            vertices += &format!(
+                r#"<vertex x="{}" y="{}" z="{}" />"#,
+                corner(1),
+                corner(2),
+                corner(4)
+            );
+        }
+        let faces = [
+            [0, 2, 3],
+            [0, 3, 1],
+            [4, 5, 7],
+            [4, 7, 6],
+            [0, 1, 5],
+            [0, 5, 4],
+            [2, 7, 3],
+            [2, 6, 7],
+            [0, 4, 6],
+            [0, 6, 2],
+            [1, 3, 7],
+            [1, 7, 5],
+        ];
+        let triangles: String = faces
+            .iter()
+            .map(|[a, b, c]| format!(r#"<triangle v1="{}" v2="{}" v3="{}" />"#, a, b, c))
+            .collect();
+        let model = format!(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<model unit="inch" xmlns="http://schemas.microsoft.com/3dmanufacturing/core/2015/02">
+  <resources>
+    <object id="1"><mesh><vertices>{}</vertices><triangles>{}</triangles></mesh></object>
+  </resources>
+  <build>
+    <item objectid="1" />
+    <item objectid="1" transform="-2 0 0 0 1 0 0 0 1 0 0 0" />
+  </build>
+</model>
+"#,
+            vertices, triangles
+        );
+
+        let mut part = load(&package(&model)).unwrap();
+        assert_eq!(part.unit, Some(Unit::Inch));
+        part.scale(Unit::Inch.millimetres());
+        let volume = crate::metrics::Metrics::new(&part).volume;
+        let expected = 3.0 * 254.0f64.powi(3);
+        assert!((volume - expected).abs() < expected * 1e-6);
+    }
+}
@@ -1,21 +1,31 @@
 mod bounding_box;
 mod graphics;
+mod loader;
+mod metrics;
+mod part;
+mod units;
+mod validate;
+mod view;
 
+use anyhow::Context;
 use bounding_box::BoundingBox;
 use graphics::screenshot;
-use std::io::BufReader;
+use part::Part;
+use std::io::Write;
+use units::Unit;
+use view::{CameraSettings, Placement, View, ViewChoice, ViewStrategy};
+
+fn main() -> anyhow::Result<()> {
+    env_logger::init();
 
-fn main() {
     let matches = clap::App::new("Part Viewer")
-        .arg(
-            clap::Arg::with_name("INPUT")
-                .help("The input STL file to use")
-                .required(true)
-                .index(1),
-        )
+        // Subcommands take their own input, so the screenshot arguments
+        // aren't required when one is used.
+        .setting(clap::AppSettings::SubcommandsNegateReqs)
+        .arg(input_arg())
         .arg(
             clap::Arg::with_name("OUTPUT")
-                .help("The output destination")
+                .help("The output destination, or - to write a PNG to stdout")
                 .required(true)
                 .index(2),
         )
@@ -31,39 +41,581 @@ fn main() {
                 .required(true)
                 .index(4),
         )
+        .arg(format_arg())
+        .arg(units_arg())
+        .arg(scale_arg())
+        .arg(
+            clap::Arg::with_name("legend")
+                .long("legend")
+                .help("Draw a legend with the name and color of each body"),
+        )
+        .arg(weld_tolerance_arg())
+        .arg(
+            clap::Arg::with_name("crease-angle")
+                .long("crease-angle")
+                .takes_value(true)
+                .value_name("DEGREES")
+                .default_value("30")
+                .help("Shade edges smoothly where the faces meet at less than this angle"),
+        )
+        .arg(
+            clap::Arg::with_name("max-triangles")
+                .long("max-triangles")
+                .takes_value(true)
+                .value_name("COUNT")
+                .help("Simplify the part to at most this many triangles [default: twice the pixel count]"),
+        )
+        .arg(
+            clap::Arg::with_name("reorient")
+                .long("reorient")
+                .help("Flip triangles so that each piece of the part winds consistently outward"),
+        )
+        .arg(clap::Arg::with_name("shells").long("shells").help(
+            "Color each separate shell of the part differently and report how many there are",
+        ))
+        .args(&camera_args())
+        .arg(
+            clap::Arg::with_name("metrics")
+                .long("metrics")
+                .help("Store the part's volume, area and extents in the PNG's metadata as JSON"),
+        )
+        .subcommand(
+            clap::SubCommand::with_name("info")
+                .about("Reports the volume, surface area, centroid and extents of the part")
+                .arg(input_arg())
+                .arg(format_arg())
+                .arg(units_arg())
+                .arg(scale_arg())
+                .arg(weld_tolerance_arg())
+                .arg(
+                    clap::Arg::with_name("json")
+                        .long("json")
+                        .help("Write the metrics as JSON"),
+                ),
+        )
+        .subcommand(
+            clap::SubCommand::with_name("validate")
+                .about("Reports problems that would stop the part from printing properly")
+                .arg(input_arg())
+                .arg(format_arg())
+                .arg(units_arg())
+                .arg(scale_arg())
+                .arg(weld_tolerance_arg())
+                .arg(
+                    clap::Arg::with_name("json")
+                        .long("json")
+                        .help("Write the report as JSON"),
+                )
+                .arg(
+                    clap::Arg::with_name("screenshot")
+                        .long("screenshot")
+                        .takes_value(true)
+                        .value_name("OUTPUT")
+                        .help("Also save a screenshot with the problems highlighted"),
+                )
+                .args(&camera_args())
+                .arg(
+                    clap::Arg::with_name("width")
+                        .long("width")
+                        .takes_value(true)
+                        .default_value("800")
+                        .help("Width of the screenshot in pixels"),
+                )
+                .arg(
+                    clap::Arg::with_name("height")
+                        .long("height")
+                        .takes_value(true)
+                        .default_value("600")
+                        .help("Height of the screenshot in pixels"),
+                ),
+        )
         .get_matches();
 
+    match matches.subcommand() {
+        ("info", Some(matches)) => report_metrics(matches),
+        ("validate", Some(matches)) => {
+            // Let scripts tell whether the part is fit to print.
+            if !report_problems(matches)? {
+                std::process::exit(1);
+            }
+            Ok(())
+        }
+        _ => render_screenshot(&matches),
+    }
+}
+
+fn input_arg<'a, 'b>() -> clap::Arg<'a, 'b> {
+    clap::Arg::with_name("INPUT")
+        .help("The input STL, OBJ, PLY, 3MF, glTF or GLB file to use, or - for stdin")
+        .required(true)
+        .index(1)
+}
+
+fn format_arg<'a, 'b>() -> clap::Arg<'a, 'b> {
+    clap::Arg::with_name("format")
+        .long("format")
+        .takes_value(true)
+        .possible_values(&loader::Format::NAMES)
+        .help("The format of the input, instead of detecting it from the contents")
+}
+
+fn units_arg<'a, 'b>() -> clap::Arg<'a, 'b> {
+    clap::Arg::with_name("units")
+        .long("units")
+        .takes_value(true)
+        .possible_values(&Unit::NAMES)
+        .help("The units of the input, instead of the ones the file declares [default: mm]")
+}
+
+fn scale_arg<'a, 'b>() -> clap::Arg<'a, 'b> {
+    clap::Arg::with_name("scale")
+        .long("scale")
+        .takes_value(true)
+        .value_name("FACTOR")
+        .help("Scale the part by this factor after converting it to millimetres")
+}
+
+fn weld_tolerance_arg<'a, 'b>() -> clap::Arg<'a, 'b> {
+    clap::Arg::with_name("weld-tolerance")
+        .long("weld-tolerance")
+        .takes_value(true)
+        .value_name("DISTANCE")
+        .help("Merge vertices closer than this many millimetres [default: 1e-5 of the part's size]")
+}
+
+/// The arguments that say where to look at the part from.
+fn camera_args<'a, 'b>() -> Vec<clap::Arg<'a, 'b>> {
+    let angle_arg = |name: &'a str, help: &'b str| {
+        clap::Arg::with_name(name)
+            .long(name)
+            .takes_value(true)
+            .allow_hyphen_values(true)
+            .value_name("DEGREES")
+            .help(help)
+    };
+    let point_arg = |name: &'a str, help: &'b str| {
+        clap::Arg::with_name(name)
+            .long(name)
+            .takes_value(true)
+            .allow_hyphen_values(true)
+            .value_name("X,Y,Z")
+            .help(help)
+    };
+    vec![
+        clap::Arg::with_name("view")
+            .long("view")
+            .takes_value(true)
+            .possible_values(&View::NAMES)
+            .help("Look at the part from this standard view instead of picking one"),
+        clap::Arg::with_name("view-strategy")
+            .long("view-strategy")
+            .takes_value(true)
+            .possible_values(&ViewStrategy::NAMES)
+            .default_value("silhouette")
+            .help("Pick the view that shows the most of the part's bounding box, silhouette or visible surface"),
+        clap::Arg::with_name("up")
+            .long("up")
+            .takes_value(true)
+            .allow_hyphen_values(true)
+            .default_value("z")
+            .help("The axis, such as y, or the direction, such as 0,1,0, that points up in the input"),
+        angle_arg(
+            "azimuth",
+            "Look from this angle around the part, from 0 at the front to 90 at the right",
+        )
+        .conflicts_with("view"),
+        angle_arg("elevation", "Look from this angle above the part").conflicts_with("view"),
+        angle_arg("roll", "Turn the camera clockwise about its line of sight by this angle"),
+        point_arg("eye", "Put the camera at this point, in the part's coordinates")
+            .conflicts_with_all(&["view", "azimuth", "elevation", "roll"]),
+        point_arg(
+            "target",
+            "Point the camera at this point, in the part's coordinates [default: the part's center]",
+        )
+        .requires("eye"),
+        clap::Arg::with_name("projection")
+            .long("projection")
+            .takes_value(true)
+            .possible_values(&["perspective", "orthographic"])
+            .default_value("perspective")
+            .help("Draw with perspective, or orthographic so that parallel edges stay parallel"),
+    ]
+}
+
+/// Load the part given by the INPUT and format arguments, and convert it to
+/// millimetres. Huge parts may be simplified toward the triangle budget, if
+/// there is one, as they're loaded.
+fn load_part(matches: &clap::ArgMatches, max_triangles: Option<usize>) -> anyhow::Result<Part> {
     let src_path = matches.value_of("INPUT").unwrap();
+    let format = matches
+        .value_of("format")
+        .and_then(loader::Format::from_name);
+    let mut part = loader::load(src_path, format, max_triangles)?;
+
+    let unit = match matches
+        .value_of("units")
+        .and_then(Unit::from_name)
+        .or(part.unit)
+    {
+        Some(unit) => unit,
+        // Most parts are modelled in millimetres, so the size of the part is
+        // only used to hint at a mistake rather than to rescale it.
+        None => {
+            let bounding_box = BoundingBox::new(&part);
+            let largest_extent = bounding_box
+                .dx()
+                .max(bounding_box.dy())
+                .max(bounding_box.dz());
+            let guess = Unit::guess(largest_extent as f32);
+            if guess != Unit::Millimetre {
+                eprintln!(
+                    "The part is only {} across, so it may be in {}. Use --units to say so",
+                    largest_extent, guess
+                );
+            }
+            Unit::Millimetre
+        }
+    };
+    let scale = match matches.value_of("scale") {
+        Some(scale) => scale
+            .parse::<f32>()
+            .ok()
+            .filter(|scale| scale.is_finite() && *scale > 0.0)
+            .with_context(|| format!("Invalid scale {}. It must be a positive number", scale))?,
+        None => 1.0,
+    };
+    let factor = unit.millimetres() * scale;
+    if factor != 1.0 {
+        part.scale(factor);
+    }
+    part.unit = Some(Unit::Millimetre);
+    Ok(part)
+}
+
+/// Weld the vertices of the part with the tolerance given by the arguments.
+fn weld_part(part: &mut Part, matches: &clap::ArgMatches) -> anyhow::Result<()> {
+    let weld_tolerance = match matches.value_of("weld-tolerance") {
+        Some(tolerance) => tolerance
+            .parse::<f32>()
+            .with_context(|| format!("Invalid weld tolerance {}", tolerance))?,
+        // Well above the rounding error of coordinates written as text, but
+        // far smaller than any feature worth rendering.
+        None => {
+            let bounding_box = BoundingBox::new(part);
+            let size =
+                (bounding_box.dx().powi(2) + bounding_box.dy().powi(2) + bounding_box.dz().powi(2))
+                    .sqrt();
+            (size * 1e-5) as f32
+        }
+    };
+    part.weld_vertices(weld_tolerance);
+    Ok(())
+}
+
+/// Weld the part and measure it. Vertices with different colors aren't
+/// welded, which would make every seam between colors look like a hole, so
+/// colors are left out.
+fn measure_part(mut part: Part, matches: &clap::ArgMatches) -> anyhow::Result<metrics::Metrics> {
+    part.colors = None;
+    weld_part(&mut part, matches)?;
+    Ok(metrics::Metrics::new(&part))
+}
+
+fn render_screenshot(matches: &clap::ArgMatches) -> anyhow::Result<()> {
     let dst_path = matches.value_of("OUTPUT").unwrap();
     let width = matches.value_of("WIDTH").unwrap().parse::<u32>().unwrap();
     let height = matches.value_of("HEIGHT").unwrap().parse::<u32>().unwrap();
+    let crease_angle = matches.value_of("crease-angle").unwrap();
+    let crease_angle = cgmath::Deg(
+        crease_angle
+            .parse::<f32>()
+            .with_context(|| format!("Invalid crease angle {}", crease_angle))?,
+    );
+    // There's no use in drawing many more triangles than there are pixels to
+    // show them, and huge scans can run the GPU out of memory.
+    let max_triangles = match matches.value_of("max-triangles") {
+        Some(count) => count
+            .parse::<usize>()
+            .with_context(|| format!("Invalid triangle count {}", count))?,
+        None => 2 * width as usize * height as usize,
+    };
+
+    // Metrics are measured on the part as it is in the file.
+    let budget = Some(max_triangles).filter(|_| !matches.is_present("metrics"));
+    let mut part = load_part(matches, budget)?;
+
+    let mut metadata = Vec::new();
+    if matches.is_present("metrics") {
+        let metrics = measure_part(part.clone(), matches)?;
+        metadata.push(("Part metrics".to_string(), serde_json::to_string(&metrics)?));
+    }
+
+    let camera = place_camera(&mut part, matches)?;
+
+    // Normals are always derived from the winding, so stored normals that
+    // disagree with it are a sign that the winding is what's wrong.
+    let normal_check = part.check_stored_normals();
+    let reorient = matches.is_present("reorient");
+    if reorient {
+        eprintln!(
+            "{} of {} facets had no stored normal and {} disagreed with their stored normal",
+            normal_check.missing,
+            part.triangle_count(),
+            normal_check.disagreeing
+        );
+    } else if normal_check.disagreeing > 0 {
+        eprintln!(
+            "{} facets disagree with their stored normal. Use --reorient to fix their winding",
+            normal_check.disagreeing
+        );
+    }
+
+    weld_part(&mut part, matches)?;
+    part.decimate(max_triangles);
+    if matches.is_present("shells") {
+        let shells = part.split_components();
+        eprintln!("The part has {} separate shells", shells);
+    }
+    if reorient {
+        let flipped = part.orient_outward();
+        eprintln!("Flipped {} facets to face outward", flipped.len());
+    }
+    part.smooth_normals(crease_angle);
+
+    save_screenshot(
+        &part,
+        dst_path,
+        width,
+        height,
+        camera,
+        matches.is_present("legend"),
+        metadata,
+    );
+    Ok(())
+}
+
+fn report_metrics(matches: &clap::ArgMatches) -> anyhow::Result<()> {
+    let metrics = measure_part(load_part(matches, None)?, matches)?;
+    let text = if matches.is_present("json") {
+        serde_json::to_string_pretty(&metrics)?
+    } else {
+        metrics.to_string()
+    };
+
+    let stdout = std::io::stdout();
+    match writeln!(stdout.lock(), "{}", text) {
+        // Whatever the metrics were piped into has stopped reading, which
+        // isn't a failure to measure the part.
+        Err(err) if err.kind() == std::io::ErrorKind::BrokenPipe => Ok(()),
+        result => Ok(result?),
+    }
+}
+
+/// Print a report of the part's problems, and return whether it has none.
+fn report_problems(matches: &clap::ArgMatches) -> anyhow::Result<bool> {
+    let mut part = load_part(matches, None)?;
+    // Vertices with different colors aren't welded, which would make every
+    // seam between colors look like a hole.
+    part.colors = None;
+    weld_part(&mut part, matches)?;
+
+    let report = validate::Report::new(&part);
+    let text = if matches.is_present("json") {
+        serde_json::to_string_pretty(&report)?
+    } else {
+        report.to_string()
+    };
 
+    let screenshot_path = matches.value_of("screenshot");
+    // Keep the report out of the way of a screenshot written to stdout.
+    if screenshot_path == Some(screenshot::STDOUT_PATH) {
+        eprintln!("{}", text);
+    } else {
+        println!("{}", text);
+    }
+
+    if let Some(dst_path) = screenshot_path {
+        let width = matches.value_of("width").unwrap();
+        let width = width
+            .parse::<u32>()
+            .with_context(|| format!("Invalid screenshot width {}", width))?;
+        let height = matches.value_of("height").unwrap();
+        let height = height
+            .parse::<u32>()
+            .with_context(|| format!("Invalid screenshot height {}", height))?;
+        let mut highlighted = report.highlight(&part);
+        let camera = place_camera(&mut highlighted, matches)?;
+        save_screenshot(
+            &highlighted,
+            dst_path,
+            width,
+            height,
+            camera,
+            true,
+            Vec::new(),
+        );
+    }
+
+    Ok(report.problem_count() == 0)
+}
+
+/// Turn the part so that the direction given by the up argument points up, and
+/// move it to the origin. Returns where the camera arguments place the camera
+/// relative to the moved part, and how it projects it.
+fn place_camera(part: &mut Part, matches: &clap::ArgMatches) -> anyhow::Result<CameraSettings> {
+    use cgmath::{EuclideanSpace, InnerSpace, Rotation};
+
+    // There'd be nothing to frame, and no distance to place the camera at.
+    if part.triangle_count() == 0 {
+        anyhow::bail!("The part has no triangles to show");
+    }
+    let bounding_box = BoundingBox::new(part);
+    if bounding_box.dx() == 0.0 && bounding_box.dy() == 0.0 && bounding_box.dz() == 0.0 {
+        anyhow::bail!("The part has no size to show");
+    }
+
+    let up = match matches.value_of("up").unwrap() {
+        "x" => cgmath::Vector3::unit_x(),
+        "y" => cgmath::Vector3::unit_y(),
+        "z" => cgmath::Vector3::unit_z(),
+        up => cgmath::Vector3::from(parse_point(up)?),
+    };
+    if up.magnitude2() == 0.0 {
+        anyhow::bail!("The up direction can't be zero");
+    }
+    let rotation = cgmath::Quaternion::between_vectors(up.normalize(), cgmath::Vector3::unit_z());
+    if up.normalize() != cgmath::Vector3::unit_z() {
+        part.rotate(rotation);
+    }
+    // The vertices are rendered in single precision, which would make parts
+    // far from the origin jitter.
+    let center = cgmath::Vector3::from(part.recenter());
+
+    let orthographic = matches.value_of("projection") == Some("orthographic");
+
+    // Points in the part's coordinates have to be moved along with it, in
+    // double precision like the part.
+    let rotation = rotation.cast::<f64>().unwrap();
+    let to_moved_part = |point: [f64; 3]| {
+        let moved = rotation.rotate_vector(cgmath::Vector3::from(point)) - center;
+        cgmath::Point3::from_vec(view::to_render_space(moved.cast::<f32>().unwrap().into()))
+    };
+    if let Some(eye) = matches.value_of("eye") {
+        let target = match matches.value_of("target") {
+            Some(target) => to_moved_part(parse_point(target)?),
+            None => cgmath::Point3::new(0.0, 0.0, 0.0),
+        };
+        let eye = to_moved_part(parse_point(eye)?);
+        if eye == target {
+            anyhow::bail!("The camera can't be at the point it's looking at");
+        }
+        return Ok(CameraSettings {
+            placement: Placement::Explicit { eye, target },
+            orthographic,
+        });
+    }
+
+    let angle = |name: &str| -> anyhow::Result<cgmath::Deg<f32>> {
+        Ok(cgmath::Deg(match matches.value_of(name) {
+            Some(angle) => angle.parse::<f32>()?,
+            None => 0.0,
+        }))
+    };
+    let choice = if matches.is_present("azimuth") || matches.is_present("elevation") {
+        ViewChoice::Fixed(View::from_angles(angle("azimuth")?, angle("elevation")?))
+    } else if let Some(view) = matches.value_of("view").and_then(View::from_name) {
+        ViewChoice::Fixed(view)
+    } else {
+        ViewChoice::Auto(
+            ViewStrategy::from_name(matches.value_of("view-strategy").unwrap()).unwrap(),
+        )
+    };
+    Ok(CameraSettings {
+        placement: Placement::Framed(choice, angle("roll")?),
+        orthographic,
+    })
+}
+
+/// Parse a point or direction written as `x,y,z`.
+fn parse_point<S>(text: &str) -> anyhow::Result<[S; 3]>
+where
+    S: std::str::FromStr + Copy,
+    S::Err: std::error::Error + Send + Sync + 'static,
+{
+    let coordinates = text
+        .split(',')
+        .map(|coordinate| coordinate.trim().parse::<S>())
+        .collect::<Result<Vec<_>, _>>()?;
+    match coordinates.as_slice() {
+        &[x, y, z] => Ok([x, y, z]),
+        _ => anyhow::bail!(
+            "Expected three coordinates separated by commas, not {}",
+            text
+        ),
+    }
+}
+
+/// Frame the part and save a screenshot of it.
+fn save_screenshot(
+    part: &Part,
+    dst_path: &str,
+    width: u32,
+    height: u32,
+    camera: CameraSettings,
+    legend: bool,
+    metadata: Vec<(String, String)>,
+) {
     let aspect = width as f32 / height as f32;
     let camera_fovy = cgmath::Deg(45.0);
 
-    let file = std::fs::File::open(&src_path).unwrap();
-    let mut reader = BufReader::new(&file);
-    let mesh = nom_stl::parse_stl(&mut reader).unwrap();
-    let mut bounding_box = BoundingBox::new(&mesh);
+    let mut bounding_box = BoundingBox::new(part);
+    if bounding_box.skipped_vertices > 0 {
+        eprintln!(
+            "{} vertices have a NaN or infinite coordinate and were left out when framing the part",
+            bounding_box.skipped_vertices
+        );
+    }
 
     // Shift the model and its bounding box so that the bounding box is centered
     // on the origin.
     let model_translation = bounding_box.center_to_origin();
     bounding_box.shift(model_translation);
 
-    let look_down_axis = bounding_box.largest_cross_section_axis();
-    let camera_position = bounding_box.pick_camera_position(aspect, camera_fovy, &look_down_axis);
-    let point_light_position = bounding_box.pick_light_position(&look_down_axis);
+    let (view, camera_position, camera_target) = match camera.placement {
+        Placement::Framed(choice, roll) => {
+            let view = choice.view(part, &bounding_box).rolled(roll);
+            let camera_position = bounding_box.pick_camera_position(aspect, camera_fovy, &view);
+            (view, camera_position, cgmath::Point3::new(0.0, 0.0, 0.0))
+        }
+        Placement::Explicit { eye, target } => (View::from_direction(eye - target), eye, target),
+    };
+    let point_light_position = bounding_box.pick_light_position(&view);
+    let camera_projection = if camera.orthographic {
+        let (width, height) = bounding_box.pick_orthographic_size(aspect, &view, camera_target);
+        graphics::camera::Projection::Orthographic { width, height }
+    } else {
+        graphics::camera::Projection::Perspective {
+            fovy: camera_fovy,
+            aspect,
+        }
+    };
+    let (camera_znear, camera_zfar) = bounding_box.pick_clipping_planes(camera_position);
 
     let descrip = screenshot::ScreenshotDescriptor {
-        mesh: &mesh,
+        part,
         dst_path,
         width,
         height,
-        model_translation,
+        model_translation: model_translation.cast().unwrap(),
         point_light_position,
         camera_position,
-        camera_fovy,
+        camera_target,
+        camera_up: view.up,
+        camera_projection,
+        camera_znear,
+        camera_zfar,
+        legend,
+        metadata,
     };
     futures::executor::block_on(screenshot::run(descrip));
 }
@@ -0,0 +1,200 @@
+use crate::part::Part;
+use cgmath::{InnerSpace, Vector3};
+use serde::Serialize;
+use std::fmt;
+
+/// A part counts as having no volume, such as when it's a single flat sheet,
+/// if its volume is below this fraction of its bounding box's.
+const FLAT_VOLUME_FRACTION: f64 = 1e-9;
+
+/// Measurements of a part, in millimetres.
+#[derive(Clone, Debug, Serialize)]
+pub struct Metrics {
+    pub triangles: usize,
+    pub vertices: usize,
+    /// Whether every edge is shared by exactly two triangles winding along it
+    /// in opposite directions, so that the surface encloses a volume.
+    pub watertight: bool,
+    /// The number of separate connected shells.
+    pub shells: usize,
+    /// The volume enclosed by the surface. This is only meaningful if the part
+    /// is watertight, and is negative if its faces point inward.
+    pub volume: f64,
+    pub surface_area: f64,
+    /// The center of mass of the enclosed volume, or the center of the surface
+    /// if it encloses no volume.
+    pub centroid: [f64; 3],
+    pub min: [f64; 3],
+    pub max: [f64; 3],
+    pub size: [f64; 3],
+}
+
+impl Metrics {
+    /// Measures a part. Triangles only count as neighbors if they share
+    /// vertices, so vertices should be welded first.
+    pub fn new(part: &Part) -> Self {
+        let positions = part
+            .positions
+            .iter()
+            .map(|&position| to_f64(position))
+            .collect::<Vec<_>>();
+        let (min, max) = match positions.first() {
+            Some(&first) => positions
+                .iter()
+                .fold((first, first), |(min, max), &position| {
+                    (component_min(min, position), component_max(max, position))
+                }),
+            None => (Vector3::new(0.0, 0.0, 0.0), Vector3::new(0.0, 0.0, 0.0)),
+        };
+        let size = max - min;
+
+        // Measuring the tetrahedra from the middle of the part rather than
+        // the origin keeps the rounding error down for parts far from it.
+        let reference = (min + max) / 2.0;
+        let mut volume = 0.0;
+        let mut volume_moment = Vector3::new(0.0, 0.0, 0.0);
+        let mut surface_area = 0.0;
+        let mut surface_moment = Vector3::new(0.0, 0.0, 0.0);
+        let corner = |vertex: u32| positions[vertex as usize] - reference;
+        for triangle in part.triangles() {
+            let (a, b, c) = (
+                corner(triangle[0]),
+                corner(triangle[1]),
+                corner(triangle[2]),
+            );
+            let tetrahedron = a.dot(b.cross(c)) / 6.0;
+            volume += tetrahedron;
+            volume_moment += (a + b + c) / 4.0 * tetrahedron;
+
+            let area = (b - a).cross(c - a).magnitude() / 2.0;
+            surface_area += area;
+            surface_moment += (a + b + c) / 3.0 * area;
+        }
+
+        let centroid = if volume.abs() > size.x * size.y * size.z * FLAT_VOLUME_FRACTION {
+            reference + volume_moment / volume
+        } else if surface_area > 0.0 {
+            reference + surface_moment / surface_area
+        } else {
+            reference
+        };
+
+        let watertight = part.triangle_count() > 0
+            && part
+                .edge_map()
+                .values()
+                .all(|uses| uses.len() == 2 && uses[0].forward != uses[1].forward);
+
+        // The positions are relative to the part's origin, which is only
+        // added back to the results so they keep all of its precision.
+        let origin = Vector3::from(part.origin);
+        Metrics {
+            triangles: part.triangle_count(),
+            vertices: part.positions.len(),
+            watertight,
+            shells: part.components().1,
+            volume,
+            surface_area,
+            centroid: (centroid + origin).into(),
+            min: (min + origin).into(),
+            max: (max + origin).into(),
+            size: size.into(),
+        }
+    }
+}
+
+impl fmt::Display for Metrics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{} triangles, {} vertices",
+            self.triangles, self.vertices
+        )?;
+        writeln!(
+            f,
+            "Watertight: {}",
+            if self.watertight { "yes" } else { "no" }
+        )?;
+        writeln!(f, "Shells: {}", self.shells)?;
+        writeln!(f, "Volume: {} mm³", self.volume)?;
+        writeln!(f, "Surface area: {} mm²", self.surface_area)?;
+        writeln!(f, "Centroid: {} mm", format_vector(self.centroid))?;
+        writeln!(f, "Min: {} mm", format_vector(self.min))?;
+        writeln!(f, "Max: {} mm", format_vector(self.max))?;
+        write!(f, "Size: {} mm", format_vector(self.size))
+    }
+}
+
+fn format_vector(vector: [f64; 3]) -> String {
+    format!("({}, {}, {})", vector[0], vector[1], vector[2])
+}
+
+fn to_f64(position: [f32; 3]) -> Vector3<f64> {
+    Vector3::new(position[0] as f64, position[1] as f64, position[2] as f64)
+}
+
+fn component_min(a: Vector3<f64>, b: Vector3<f64>) -> Vector3<f64> {
+    Vector3::new(a.x.min(b.x), a.y.min(b.y), a.z.min(b.z))
+}
+
+fn component_max(a: Vector3<f64>, b: Vector3<f64>) -> Vector3<f64> {
+    Vector3::new(a.x.max(b.x), a.y.max(b.y), a.z.max(b.z))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The outward faces of a cube whose corner `i` is at `x = i & 1`,
+    /// `y = i >> 1 & 1` and `z = i >> 2 & 1`.
+    const CUBE_FACES: [[u32; 3]; 12] = [
+        [0, 2, 3],
+        [0, 3, 1],
+        [4, 5, 7],
+        [4, 7, 6],
+        [0, 1, 5],
+        [0, 5, 4],
+        [2, 7, 3],
+        [2, 6, 7],
+        [0, 4, 6],
+        [0, 6, 2],
+        [1, 3, 7],
+        [1, 7, 5],
+    ];
+
+    /// A cube two units across, from 1 to 3 along each axis.
+    fn cube(faces: &[[u32; 3]]) -> Part {
+        let mut part = Part::new();
+        let body = part.add_body("Cube");
+        for i in 0..8 {
+            let corner = |bit: u32| if i & bit == 0 { 1.0 } else { 3.0 };
+            part.add_vertex([corner(1), corner(2), corner(4)], [0.0, 0.0, 0.0]);
+        }
+        for &face in faces {
+            part.add_triangle(face, body);
+        }
+        part
+    }
+
+    #[test]
+    fn a_closed_cube_is_measured() {
+        let metrics = Metrics::new(&cube(&CUBE_FACES));
+        assert_eq!((metrics.triangles, metrics.vertices), (12, 8));
+        assert!(metrics.watertight);
+        assert!((metrics.volume - 8.0).abs() < 1e-9);
+        assert!((metrics.surface_area - 24.0).abs() < 1e-9);
+        for axis in 0..3 {
+            assert!((metrics.centroid[axis] - 2.0).abs() < 1e-9);
+            assert_eq!(metrics.min[axis], 1.0);
+            assert_eq!(metrics.max[axis], 3.0);
+            assert_eq!(metrics.size[axis], 2.0);
+        }
+    }
+
+    #[test]
+    fn an_open_cube_is_not_watertight() {
+        let metrics = Metrics::new(&cube(&CUBE_FACES[2..]));
+        assert!(!metrics.watertight);
+        assert!((metrics.surface_area - 20.0).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,45 @@
+/// Colors that are easy to tell apart, as sRGB, for parts whose bodies don't
+/// have colors of their own.
+const PALETTE: [[u8; 3]; 10] = [
+    [0x4e, 0x79, 0xa7],
+    [0xf2, 0x8e, 0x2b],
+    [0xe1, 0x57, 0x59],
+    [0x76, 0xb7, 0xb2],
+    [0x59, 0xa1, 0x4f],
+    [0xed, 0xc9, 0x48],
+    [0xb0, 0x7a, 0xa1],
+    [0xff, 0x9d, 0xa7],
+    [0x9c, 0x75, 0x5f],
+    [0xba, 0xb0, 0xac],
+];
+
+/// Returns the linear RGB color of the given entry of the palette. The palette
+/// repeats once every color has been used.
+pub fn palette_color(index: usize) -> [f32; 3] {
+    let [r, g, b] = PALETTE[index % PALETTE.len()];
+    [
+        srgb_to_linear(r as f32 / 255.0),
+        srgb_to_linear(g as f32 / 255.0),
+        srgb_to_linear(b as f32 / 255.0),
+    ]
+}
+
+/// Converts an sRGB encoded color channel in the range [0, 1] to linear RGB,
+/// which is what the renderer expects.
+pub fn srgb_to_linear(channel: f32) -> f32 {
+    if channel <= 0.04045 {
+        channel / 12.92
+    } else {
+        ((channel + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+/// Converts a linear RGB color channel in the range [0, 1] to sRGB, which is
+/// how colors are stored in the output image.
+pub fn linear_to_srgb(channel: f32) -> f32 {
+    if channel <= 0.0031308 {
+        channel * 12.92
+    } else {
+        1.055 * channel.powf(1.0 / 2.4) - 0.055
+    }
+}
@@ -0,0 +1,115 @@
+use super::color::palette_color;
+use super::Part;
+use std::collections::HashMap;
+
+impl Part {
+    /// Returns the connected shell that each triangle belongs to, numbered in
+    /// the order they first appear, along with the number of shells.
+    /// Triangles are connected if they share a vertex, so vertices should be
+    /// welded first.
+    ///
+    /// Welding merges a shell that was accidentally exported twice into the
+    /// original, so repeated copies of a triangle are put in shells of their
+    /// own rather than joined to the first copy.
+    pub fn components(&self) -> (Vec<usize>, usize) {
+        let mut parents = (0..self.positions.len()).collect::<Vec<_>>();
+        for triangle in self.triangles() {
+            let root = find_root(&mut parents, triangle[0] as usize);
+            for &vertex in triangle[1..].iter() {
+                let other = find_root(&mut parents, vertex as usize);
+                parents[other] = root;
+            }
+        }
+
+        let mut copies: HashMap<[u32; 3], usize> = HashMap::new();
+        let mut numbers = HashMap::new();
+        let mut components = Vec::with_capacity(self.triangle_count());
+        for triangle in self.triangles() {
+            let mut sorted = triangle;
+            sorted.sort_unstable();
+            let copy = copies.entry(sorted).or_insert(0);
+            let key = (find_root(&mut parents, triangle[0] as usize), *copy);
+            *copy += 1;
+
+            let next = numbers.len();
+            components.push(*numbers.entry(key).or_insert(next));
+        }
+        (components, numbers.len())
+    }
+
+    /// Replaces the bodies of the part with one for each connected shell, each
+    /// colored from the palette, and returns the number of shells.
+    pub fn split_components(&mut self) -> usize {
+        let (components, count) = self.components();
+
+        // Vertices shared by repeated copies of a triangle are copied, so
+        // that each shell can have its own color.
+        let mut split = Part {
+            unit: self.unit,
+            origin: self.origin,
+            ..Part::new()
+        };
+        for i in 0..count {
+            split.add_body(&format!("Shell {}", i));
+        }
+        let mut vertices = HashMap::new();
+        for (triangle, &component) in self.triangles().zip(components.iter()) {
+            let mut corners = [0; 3];
+            for (corner, &vertex) in corners.iter_mut().zip(triangle.iter()) {
+                *corner = *vertices.entry((vertex, component)).or_insert_with(|| {
+                    split.add_colored_vertex(
+                        self.positions[vertex as usize],
+                        self.normals[vertex as usize],
+                        palette_color(component),
+                    )
+                });
+            }
+            split.add_triangle(corners, component as u32);
+        }
+
+        *self = split;
+        count
+    }
+}
+
+/// Returns the representative of the set containing the given element,
+/// shortening the path to it along the way.
+fn find_root(parents: &mut [usize], mut element: usize) -> usize {
+    while parents[element] != element {
+        parents[element] = parents[parents[element]];
+        element = parents[element];
+    }
+    element
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shells_are_found_and_colored() {
+        let mut part = Part::new();
+        let body = part.add_body("Part");
+        for i in 0..8 {
+            part.add_vertex([i as f32, (i % 2) as f32, 0.0], [0.0, 0.0, 1.0]);
+        }
+        // The last triangle touches the first at a vertex, and the third is
+        // the first exported again.
+        for &triangle in &[[0, 1, 2], [3, 4, 5], [0, 1, 2], [2, 6, 7]] {
+            part.add_triangle(triangle, body);
+        }
+        assert_eq!(part.components(), (vec![0, 1, 2, 0], 3));
+
+        assert_eq!(part.split_components(), 3);
+        assert_eq!(part.bodies.len(), 3);
+        assert_eq!(part.triangle_bodies, [0, 1, 2, 0]);
+        // The repeated triangle has its own vertices, in its own color.
+        assert_eq!(part.positions.len(), 11);
+        let colors = part.colors.as_ref().unwrap();
+        for (triangle, body) in part.triangles().zip(part.triangle_bodies.iter()) {
+            for &vertex in triangle.iter() {
+                assert_eq!(colors[vertex as usize], palette_color(*body as usize));
+            }
+        }
+    }
+}
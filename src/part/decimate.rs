@@ -0,0 +1,493 @@
+use super::Part;
+use cgmath::{InnerSpace, Matrix3, SquareMatrix, Vector3};
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+use std::ops::Add;
+
+/// Planes through boundary edges, which keep the edges of open surfaces and
+/// seams between colors in place, weigh this many times as much as faces.
+const BOUNDARY_WEIGHT: f64 = 1000.0;
+
+/// A collapse is rejected if it would turn one of the surrounding faces so far
+/// that the cosine of the angle it turns through drops below this, since the
+/// face would likely fold over its neighbors.
+const MIN_TURN_COSINE: f64 = 0.2;
+
+/// The sum of the squared distances from a point to a set of planes, stored
+/// as the upper triangle of a symmetric 4x4 matrix.
+#[derive(Clone, Copy, Debug, Default)]
+struct Quadric {
+    xx: f64,
+    xy: f64,
+    xz: f64,
+    xw: f64,
+    yy: f64,
+    yz: f64,
+    yw: f64,
+    zz: f64,
+    zw: f64,
+    ww: f64,
+}
+
+impl Quadric {
+    /// The quadric of the plane through `point` with the unit `normal`.
+    fn plane(normal: Vector3<f64>, point: Vector3<f64>, weight: f64) -> Self {
+        let (a, b, c) = (normal.x, normal.y, normal.z);
+        let d = -normal.dot(point);
+        Quadric {
+            xx: weight * a * a,
+            xy: weight * a * b,
+            xz: weight * a * c,
+            xw: weight * a * d,
+            yy: weight * b * b,
+            yz: weight * b * c,
+            yw: weight * b * d,
+            zz: weight * c * c,
+            zw: weight * c * d,
+            ww: weight * d * d,
+        }
+    }
+
+    fn error(&self, p: Vector3<f64>) -> f64 {
+        p.x * (self.xx * p.x + self.xy * p.y + self.xz * p.z)
+            + p.y * (self.xy * p.x + self.yy * p.y + self.yz * p.z)
+            + p.z * (self.xz * p.x + self.yz * p.y + self.zz * p.z)
+            + 2.0 * (self.xw * p.x + self.yw * p.y + self.zw * p.z)
+            + self.ww
+    }
+
+    /// Returns the point with the least error, unless the planes are close
+    /// enough to parallel that there isn't a single best point.
+    fn optimum(&self) -> Option<Vector3<f64>> {
+        let matrix = Matrix3::new(
+            self.xx, self.xy, self.xz, self.xy, self.yy, self.yz, self.xz, self.yz, self.zz,
+        );
+        let scale = self.xx + self.yy + self.zz;
+        if matrix.determinant().abs() <= 1e-10 * scale * scale * scale {
+            return None;
+        }
+        matrix
+            .invert()
+            .map(|inverse| inverse * -Vector3::new(self.xw, self.yw, self.zw))
+    }
+}
+
+impl Add for Quadric {
+    type Output = Quadric;
+
+    fn add(self, other: Quadric) -> Quadric {
+        Quadric {
+            xx: self.xx + other.xx,
+            xy: self.xy + other.xy,
+            xz: self.xz + other.xz,
+            xw: self.xw + other.xw,
+            yy: self.yy + other.yy,
+            yz: self.yz + other.yz,
+            yw: self.yw + other.yw,
+            zz: self.zz + other.zz,
+            zw: self.zw + other.zw,
+            ww: self.ww + other.ww,
+        }
+    }
+}
+
+/// Merging the second vertex of an edge into the first. Collapses are ordered
+/// so that the cheapest is taken from the heap first, and are kept small since
+/// there are a great many of them.
+#[derive(PartialEq, Eq)]
+struct Collapse {
+    /// The bits of the non-negative cost, which sort the same way as the cost.
+    cost: u64,
+    edge: (u32, u32),
+    /// The versions of the vertices when the collapse was planned. It's out of
+    /// date once either vertex has been moved.
+    versions: (u32, u32),
+}
+
+impl PartialOrd for Collapse {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Collapse {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.cost.cmp(&self.cost)
+    }
+}
+
+/// The state of a part partway through being simplified.
+struct Decimation {
+    positions: Vec<Vector3<f64>>,
+    quadrics: Vec<Quadric>,
+    triangles: Vec<[u32; 3]>,
+    removed: Vec<bool>,
+    /// The triangles around each vertex. Removed triangles are left in these
+    /// lists and skipped.
+    vertex_triangles: Vec<Vec<u32>>,
+    versions: Vec<u32>,
+    merged: Vec<bool>,
+    remaining: usize,
+    collapses: BinaryHeap<Collapse>,
+}
+
+impl Decimation {
+    fn new(part: &Part) -> Self {
+        let positions = part
+            .positions
+            .iter()
+            .map(|&[x, y, z]| Vector3::new(x as f64, y as f64, z as f64))
+            .collect::<Vec<_>>();
+        let triangles = part.triangles().collect::<Vec<_>>();
+
+        let mut quadrics = vec![Quadric::default(); positions.len()];
+        let mut vertex_triangles = vec![Vec::new(); positions.len()];
+        let mut face_normals = Vec::with_capacity(triangles.len());
+        for (i, triangle) in triangles.iter().enumerate() {
+            let [a, b, c] = corner_positions(&positions, *triangle);
+            let normal = (b - a).cross(c - a);
+            let area = normal.magnitude() / 2.0;
+            let normal = if area > 0.0 {
+                normal.normalize()
+            } else {
+                normal
+            };
+            face_normals.push(normal);
+            for &vertex in triangle.iter() {
+                let quadric = &mut quadrics[vertex as usize];
+                *quadric = *quadric + Quadric::plane(normal, a, area);
+                vertex_triangles[vertex as usize].push(i as u32);
+            }
+        }
+
+        let edges = part.edge_map();
+        for (&(a, b), uses) in edges.iter() {
+            if uses.len() != 1 {
+                continue;
+            }
+            let (pa, pb) = (positions[a as usize], positions[b as usize]);
+            let side = (pb - pa).cross(face_normals[uses[0].triangle]);
+            if side.magnitude2() == 0.0 {
+                continue;
+            }
+            let quadric = Quadric::plane(
+                side.normalize(),
+                pa,
+                BOUNDARY_WEIGHT * (pb - pa).magnitude2(),
+            );
+            quadrics[a as usize] = quadrics[a as usize] + quadric;
+            quadrics[b as usize] = quadrics[b as usize] + quadric;
+        }
+
+        let mut decimation = Decimation {
+            versions: vec![0; positions.len()],
+            merged: vec![false; positions.len()],
+            removed: vec![false; triangles.len()],
+            remaining: triangles.len(),
+            positions,
+            quadrics,
+            triangles,
+            vertex_triangles,
+            collapses: BinaryHeap::new(),
+        };
+        // Building the heap all at once is quicker than pushing each edge.
+        let collapses = edges
+            .keys()
+            .filter_map(|&(a, b)| decimation.planned(a, b))
+            .collect::<Vec<_>>();
+        decimation.collapses = BinaryHeap::from(collapses);
+        decimation
+    }
+
+    /// Returns where the vertices of an edge would best be merged, and the
+    /// error that merging them there would cause.
+    fn best_collapse(&self, a: u32, b: u32) -> (Vector3<f64>, f64) {
+        let quadric = self.quadrics[a as usize] + self.quadrics[b as usize];
+        let (pa, pb) = (self.positions[a as usize], self.positions[b as usize]);
+        let position = quadric.optimum().unwrap_or_else(|| {
+            let midpoint = (pa + pb) / 2.0;
+            [pa, pb, midpoint]
+                .iter()
+                .copied()
+                .min_by(|p, q| {
+                    quadric
+                        .error(*p)
+                        .partial_cmp(&quadric.error(*q))
+                        .unwrap_or(Ordering::Equal)
+                })
+                .unwrap()
+        });
+        // Rounding can leave the error just below zero. A NaN error is kept as
+        // it is, rather than clamped, so that the edge isn't collapsed first.
+        let error = quadric.error(position);
+        (position, if error < 0.0 { 0.0 } else { error })
+    }
+
+    /// Plans the collapse of an edge, unless its cost can't be worked out,
+    /// such as when a vertex near it isn't finite. Collapsing it would spread
+    /// the NaN through the rest of the part.
+    fn planned(&self, a: u32, b: u32) -> Option<Collapse> {
+        let (_, cost) = self.best_collapse(a, b);
+        if !cost.is_finite() {
+            return None;
+        }
+        Some(Collapse {
+            cost: cost.to_bits(),
+            edge: (a, b),
+            versions: (self.versions[a as usize], self.versions[b as usize]),
+        })
+    }
+
+    /// Returns the vertices that share a live triangle with the given one.
+    fn neighbors(&self, vertex: u32) -> Vec<u32> {
+        let mut neighbors = self.vertex_triangles[vertex as usize]
+            .iter()
+            .filter(|&&triangle| !self.removed[triangle as usize])
+            .flat_map(|&triangle| self.triangles[triangle as usize].to_vec())
+            .filter(|&other| other != vertex)
+            .collect::<Vec<_>>();
+        neighbors.sort_unstable();
+        neighbors.dedup();
+        neighbors
+    }
+
+    /// Checks that merging the vertices at the given position wouldn't pinch
+    /// the surface or fold any of the faces around them over.
+    fn can_collapse(&self, a: u32, b: u32, position: Vector3<f64>) -> bool {
+        let a_neighbors = self.neighbors(a);
+        let shared_neighbors = self
+            .neighbors(b)
+            .iter()
+            .filter(|vertex| a_neighbors.binary_search(vertex).is_ok())
+            .count();
+        let shared_triangles = self.vertex_triangles[a as usize]
+            .iter()
+            .filter(|&&triangle| {
+                !self.removed[triangle as usize] && self.triangles[triangle as usize].contains(&b)
+            })
+            .count();
+        if shared_neighbors != shared_triangles {
+            return false;
+        }
+
+        for &vertex in [a, b].iter() {
+            for &triangle in self.vertex_triangles[vertex as usize].iter() {
+                let corners = self.triangles[triangle as usize];
+                if self.removed[triangle as usize] || (corners.contains(&a) && corners.contains(&b))
+                {
+                    continue;
+                }
+                let [p, q, r] = corner_positions(&self.positions, corners);
+                let before = (q - p).cross(r - p);
+                let mut moved = [p, q, r];
+                for (corner, &index) in moved.iter_mut().zip(corners.iter()) {
+                    if index == vertex {
+                        *corner = position;
+                    }
+                }
+                let after = (moved[1] - moved[0]).cross(moved[2] - moved[0]);
+                if after.magnitude2() == 0.0
+                    || before.dot(after) < MIN_TURN_COSINE * before.magnitude() * after.magnitude()
+                {
+                    return false;
+                }
+            }
+        }
+        true
+    }
+
+    /// Merges `b` into `a`, removing the triangles between them.
+    fn collapse(&mut self, a: u32, b: u32, position: Vector3<f64>) {
+        self.positions[a as usize] = position;
+        self.quadrics[a as usize] = self.quadrics[a as usize] + self.quadrics[b as usize];
+        self.merged[b as usize] = true;
+        self.versions[a as usize] += 1;
+
+        let b_triangles = std::mem::take(&mut self.vertex_triangles[b as usize]);
+        for triangle in b_triangles {
+            if self.removed[triangle as usize] {
+                continue;
+            }
+            let corners = &mut self.triangles[triangle as usize];
+            if corners.contains(&a) {
+                self.removed[triangle as usize] = true;
+                self.remaining -= 1;
+            } else {
+                for corner in corners.iter_mut().filter(|corner| **corner == b) {
+                    *corner = a;
+                }
+                self.vertex_triangles[a as usize].push(triangle);
+            }
+        }
+        let removed = &self.removed;
+        self.vertex_triangles[a as usize].retain(|&triangle| !removed[triangle as usize]);
+
+        for neighbor in self.neighbors(a) {
+            if let Some(collapse) = self.planned(a, neighbor) {
+                self.collapses.push(collapse);
+            }
+        }
+    }
+}
+
+impl Part {
+    /// Simplifies the part down to at most `max_triangles` triangles by
+    /// repeatedly collapsing the edge whose removal changes the shape the
+    /// least, as measured by quadric error metrics. Boundaries, including the
+    /// seams between differently colored vertices, are kept where they are.
+    ///
+    /// Collapses are only found along shared edges, so vertices should be
+    /// welded first. The part may be left with more triangles than asked for
+    /// if no more edges can be collapsed without folding it over.
+    pub fn decimate(&mut self, max_triangles: usize) {
+        if self.triangle_count() <= max_triangles {
+            return;
+        }
+        let original_count = self.triangle_count();
+
+        let mut decimation = Decimation::new(self);
+        while decimation.remaining > max_triangles {
+            let collapse = match decimation.collapses.pop() {
+                Some(collapse) => collapse,
+                None => break,
+            };
+            let (a, b) = collapse.edge;
+            if decimation.merged[a as usize]
+                || decimation.merged[b as usize]
+                || collapse.versions
+                    != (
+                        decimation.versions[a as usize],
+                        decimation.versions[b as usize],
+                    )
+            {
+                continue;
+            }
+            let (position, _) = decimation.best_collapse(a, b);
+            if decimation.can_collapse(a, b, position) {
+                decimation.collapse(a, b, position);
+            }
+        }
+
+        let mut decimated = Part {
+            colors: self.colors.as_ref().map(|_| Vec::new()),
+            bodies: self.bodies.clone(),
+            unit: self.unit,
+            origin: self.origin,
+            ..Part::new()
+        };
+        let mut new_indices = vec![None; self.positions.len()];
+        for (i, triangle) in decimation.triangles.iter().enumerate() {
+            if decimation.removed[i] {
+                continue;
+            }
+            let mut corners = [0; 3];
+            for (corner, &vertex) in corners.iter_mut().zip(triangle.iter()) {
+                *corner = *new_indices[vertex as usize].get_or_insert_with(|| {
+                    let position = decimation.positions[vertex as usize];
+                    let position = [position.x as f32, position.y as f32, position.z as f32];
+                    let normal = self.normals[vertex as usize];
+                    match &self.colors {
+                        Some(colors) => {
+                            decimated.add_colored_vertex(position, normal, colors[vertex as usize])
+                        }
+                        None => decimated.add_vertex(position, normal),
+                    }
+                });
+            }
+            decimated.add_triangle(corners, self.triangle_bodies[i]);
+        }
+
+        log::info!(
+            "Decimated the part from {} to {} triangles",
+            original_count,
+            decimated.triangle_count()
+        );
+        if decimated.triangle_count() > max_triangles {
+            log::warn!(
+                "Couldn't simplify the part below {} triangles without folding it over",
+                decimated.triangle_count()
+            );
+        }
+        *self = decimated;
+    }
+}
+
+fn corner_positions(positions: &[Vector3<f64>], corners: [u32; 3]) -> [Vector3<f64>; 3] {
+    [
+        positions[corners[0] as usize],
+        positions[corners[1] as usize],
+        positions[corners[2] as usize],
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::part::face_normal;
+
+    /// A flat square, `size` units across, split into a grid of unit squares
+    /// that share their corners.
+    fn grid(size: u32) -> Part {
+        let mut part = Part::new();
+        let body = part.add_body("Grid");
+        for y in 0..=size {
+            for x in 0..=size {
+                part.add_vertex([x as f32, y as f32, 0.0], [0.0, 0.0, 1.0]);
+            }
+        }
+        let vertex = |x: u32, y: u32| y * (size + 1) + x;
+        for y in 0..size {
+            for x in 0..size {
+                let corners = [
+                    vertex(x, y),
+                    vertex(x + 1, y),
+                    vertex(x + 1, y + 1),
+                    vertex(x, y + 1),
+                ];
+                part.add_triangle([corners[0], corners[1], corners[2]], body);
+                part.add_triangle([corners[0], corners[2], corners[3]], body);
+            }
+        }
+        part
+    }
+
+    #[test]
+    fn parts_within_the_budget_are_unchanged() {
+        let mut part = grid(2);
+        part.decimate(8);
+        assert_eq!(part.triangle_count(), 8);
+        assert_eq!(part.positions.len(), 9);
+    }
+
+    #[test]
+    fn a_flat_grid_keeps_its_outline() {
+        let mut part = grid(8);
+        part.decimate(32);
+        assert!(part.triangle_count() <= 32);
+        assert!(part.triangle_count() > 0);
+
+        for position in part.positions.iter() {
+            assert_eq!(position[2], 0.0);
+        }
+        for corner in &[
+            [0.0, 0.0, 0.0],
+            [8.0, 0.0, 0.0],
+            [0.0, 8.0, 0.0],
+            [8.0, 8.0, 0.0],
+        ] {
+            assert!(part.positions.contains(corner));
+        }
+        // None of the remaining triangles were turned over.
+        for triangle in part.triangles() {
+            assert!(face_normal(&part.triangle_positions(triangle))[2] > 0.0);
+        }
+        let area = part
+            .triangles()
+            .map(|triangle| {
+                let [a, b, c] = part.triangle_positions(triangle);
+                ((b[0] - a[0]) * (c[1] - a[1]) - (c[0] - a[0]) * (b[1] - a[1])) / 2.0
+            })
+            .sum::<f32>();
+        assert!((area - 64.0).abs() < 1e-3);
+    }
+}
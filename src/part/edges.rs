@@ -0,0 +1,46 @@
+use super::Part;
+use std::collections::HashMap;
+
+/// A triangle that has an edge as one of its sides.
+#[derive(Clone, Copy, Debug)]
+pub struct EdgeUse {
+    pub triangle: usize,
+    /// Whether the triangle's winding runs along the edge from its lower
+    /// vertex index to its higher one.
+    pub forward: bool,
+}
+
+/// The triangles on each side of every edge, keyed by the edge's vertices in
+/// ascending order.
+pub type EdgeMap = HashMap<(u32, u32), Vec<EdgeUse>>;
+
+impl Part {
+    /// Returns the triangles that use each edge. Triangles only share edges
+    /// if they share vertices, so vertices should be welded first.
+    pub fn edge_map(&self) -> EdgeMap {
+        let mut edges = EdgeMap::new();
+        for (triangle, corners) in self.triangles().enumerate() {
+            for &(a, b) in triangle_edges(corners).iter() {
+                edges.entry(edge_key(a, b)).or_default().push(EdgeUse {
+                    triangle,
+                    forward: a < b,
+                });
+            }
+        }
+        edges
+    }
+}
+
+/// Returns the directed edges of a triangle, following its winding.
+pub fn triangle_edges(corners: [u32; 3]) -> [(u32, u32); 3] {
+    [
+        (corners[0], corners[1]),
+        (corners[1], corners[2]),
+        (corners[2], corners[0]),
+    ]
+}
+
+/// Returns the key of the edge between two vertices in an `EdgeMap`.
+pub fn edge_key(a: u32, b: u32) -> (u32, u32) {
+    (a.min(b), a.max(b))
+}
@@ -0,0 +1,329 @@
+pub mod color;
+mod components;
+mod decimate;
+pub mod edges;
+mod normals;
+mod orient;
+mod weld;
+
+use crate::bounding_box::BoundingBox;
+use crate::units::Unit;
+use cgmath::{InnerSpace, Quaternion, Rotation, Vector3};
+
+/// The color given to vertices when the input file doesn't specify one.
+pub const DEFAULT_COLOR: [f32; 3] = [1.0, 1.0, 1.0];
+
+/// Parts with a coordinate at least this far from zero get an origin of their
+/// own, since single precision only keeps a few digits after the point of
+/// coordinates this large.
+const FAR_FROM_ORIGIN: f64 = 1e4;
+
+/// A named body within a part, such as an object in an OBJ file.
+#[derive(Clone, Debug)]
+pub struct Body {
+    pub name: String,
+}
+
+/// An indexed triangle mesh. Every loader produces a `Part`, and every stage
+/// of the renderer consumes one, so none of them need to know which file
+/// format the part came from.
+#[derive(Clone, Debug, Default)]
+pub struct Part {
+    /// Position of each vertex, relative to `origin`.
+    pub positions: Vec<[f32; 3]>,
+    /// Normal of each vertex.
+    pub normals: Vec<[f32; 3]>,
+    /// Linear RGB color of each vertex, if the input file specified colors.
+    pub colors: Option<Vec<[f32; 3]>>,
+    /// Vertex indices, three per counter-clockwise triangle.
+    pub indices: Vec<u32>,
+    /// Index into `bodies` of the body each triangle belongs to.
+    pub triangle_bodies: Vec<u32>,
+    pub bodies: Vec<Body>,
+    /// The unit the file said the part was modelled in, if it said.
+    pub unit: Option<Unit>,
+    /// Where the positions are measured from, in double precision, so that
+    /// parts modelled far from the origin keep their detail.
+    pub origin: [f64; 3],
+}
+
+impl Part {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Reserves room for the given number of vertices and triangles, so that
+    /// loading a huge part doesn't briefly need twice the memory to grow it.
+    pub fn reserve(&mut self, vertices: usize, triangles: usize) {
+        self.positions.reserve_exact(vertices);
+        self.normals.reserve_exact(vertices);
+        if let Some(colors) = &mut self.colors {
+            colors.reserve_exact(vertices);
+        }
+        self.indices.reserve_exact(triangles * 3);
+        self.triangle_bodies.reserve_exact(triangles);
+    }
+
+    /// Adds a body to the part and returns its ID.
+    pub fn add_body(&mut self, name: &str) -> u32 {
+        self.bodies.push(Body {
+            name: name.to_string(),
+        });
+        self.bodies.len() as u32 - 1
+    }
+
+    /// Makes the given position the part's origin if it doesn't have one yet
+    /// and the position is far from zero. The vertices already in the part
+    /// are moved so that they stay where they were. Loaders that read
+    /// positions in double precision pass each of them here before converting
+    /// any with `local_position`.
+    pub fn place_origin(&mut self, position: [f64; 3]) {
+        if self.origin != [0.0; 3]
+            || !position.iter().all(|coordinate| coordinate.is_finite())
+            || !position
+                .iter()
+                .any(|coordinate| coordinate.abs() >= FAR_FROM_ORIGIN)
+        {
+            return;
+        }
+        self.origin = [
+            position[0].round(),
+            position[1].round(),
+            position[2].round(),
+        ];
+        for position in self.positions.iter_mut() {
+            for (coordinate, origin) in position.iter_mut().zip(self.origin.iter()) {
+                *coordinate = (*coordinate as f64 - origin) as f32;
+            }
+        }
+    }
+
+    /// Returns the given position, which a loader read in double precision,
+    /// relative to the part's origin.
+    pub fn local_position(&self, position: [f64; 3]) -> [f32; 3] {
+        [
+            (position[0] - self.origin[0]) as f32,
+            (position[1] - self.origin[1]) as f32,
+            (position[2] - self.origin[2]) as f32,
+        ]
+    }
+
+    /// Returns the position of the given vertex in double precision, with the
+    /// part's origin added back.
+    pub fn absolute_position(&self, vertex: u32) -> [f64; 3] {
+        let position = self.positions[vertex as usize];
+        [
+            self.origin[0] + position[0] as f64,
+            self.origin[1] + position[1] as f64,
+            self.origin[2] + position[2] as f64,
+        ]
+    }
+
+    /// Adds a vertex to the part and returns its index.
+    pub fn add_vertex(&mut self, position: [f32; 3], normal: [f32; 3]) -> u32 {
+        self.positions.push(position);
+        self.normals.push(normal);
+        if let Some(colors) = &mut self.colors {
+            colors.push(DEFAULT_COLOR);
+        }
+        self.positions.len() as u32 - 1
+    }
+
+    /// Adds a colored vertex to the part and returns its index. Vertices that
+    /// were added without a color are given the default color.
+    pub fn add_colored_vertex(
+        &mut self,
+        position: [f32; 3],
+        normal: [f32; 3],
+        color: [f32; 3],
+    ) -> u32 {
+        let vertex_count = self.positions.len();
+        self.positions.push(position);
+        self.normals.push(normal);
+        self.colors
+            .get_or_insert_with(|| vec![DEFAULT_COLOR; vertex_count])
+            .push(color);
+        self.positions.len() as u32 - 1
+    }
+
+    /// Adds a triangle made of three existing vertices to the given body.
+    pub fn add_triangle(&mut self, indices: [u32; 3], body: u32) {
+        self.indices.extend_from_slice(&indices);
+        self.triangle_bodies.push(body);
+    }
+
+    /// Adds a triangle with its own three vertices, all of which share the
+    /// given normal.
+    pub fn add_flat_triangle(&mut self, vertices: [[f32; 3]; 3], normal: [f32; 3], body: u32) {
+        let a = self.add_vertex(vertices[0], normal);
+        let b = self.add_vertex(vertices[1], normal);
+        let c = self.add_vertex(vertices[2], normal);
+        self.add_triangle([a, b, c], body);
+    }
+
+    /// Adds a triangle with its own three vertices, all of which share the
+    /// given normal and color.
+    pub fn add_colored_flat_triangle(
+        &mut self,
+        vertices: [[f32; 3]; 3],
+        normal: [f32; 3],
+        color: [f32; 3],
+        body: u32,
+    ) {
+        let a = self.add_colored_vertex(vertices[0], normal, color);
+        let b = self.add_colored_vertex(vertices[1], normal, color);
+        let c = self.add_colored_vertex(vertices[2], normal, color);
+        self.add_triangle([a, b, c], body);
+    }
+
+    /// Scales the part about the origin. The part's own origin is scaled in
+    /// double precision, so the vertices don't move further from it than
+    /// their size.
+    pub fn scale(&mut self, factor: f32) {
+        for position in self.positions.iter_mut() {
+            for coordinate in position.iter_mut() {
+                *coordinate *= factor;
+            }
+        }
+        for coordinate in self.origin.iter_mut() {
+            *coordinate *= factor as f64;
+        }
+    }
+
+    /// Rotates the part about the origin. The part's own origin is rotated in
+    /// double precision.
+    pub fn rotate(&mut self, rotation: Quaternion<f32>) {
+        for vector in self.positions.iter_mut().chain(self.normals.iter_mut()) {
+            *vector = rotation.rotate_vector(Vector3::from(*vector)).into();
+        }
+        self.origin = rotation
+            .cast::<f64>()
+            .unwrap()
+            .rotate_vector(Vector3::from(self.origin))
+            .into();
+    }
+
+    /// Moves the part so that the center of its bounds is at the origin, and
+    /// makes the origin its own. The bounds and the moved positions are worked
+    /// out in double precision, so that parts modelled far from the origin
+    /// keep all of their detail once they're near it. Vertices with a NaN or
+    /// infinite coordinate don't count toward the bounds. Returns the center
+    /// that was moved to the origin.
+    pub fn recenter(&mut self) -> [f64; 3] {
+        let center = BoundingBox::in_part_space(self).center();
+        let offset = [
+            center.x - self.origin[0],
+            center.y - self.origin[1],
+            center.z - self.origin[2],
+        ];
+        for position in self.positions.iter_mut() {
+            for (coordinate, offset) in position.iter_mut().zip(offset.iter()) {
+                *coordinate = (*coordinate as f64 - offset) as f32;
+            }
+        }
+        self.origin = [0.0; 3];
+        center.into()
+    }
+
+    pub fn triangle_count(&self) -> usize {
+        self.indices.len() / 3
+    }
+
+    /// Returns the vertex indices of each triangle.
+    pub fn triangles(&self) -> impl Iterator<Item = [u32; 3]> + '_ {
+        self.indices
+            .chunks_exact(3)
+            .map(|triangle| [triangle[0], triangle[1], triangle[2]])
+    }
+
+    /// Returns a color that represents the given body, which is the color of
+    /// its first vertex.
+    pub fn body_color(&self, body: u32) -> [f32; 3] {
+        let colors = match &self.colors {
+            Some(colors) => colors,
+            None => return DEFAULT_COLOR,
+        };
+        self.triangle_bodies
+            .iter()
+            .position(|&triangle_body| triangle_body == body)
+            .map_or(DEFAULT_COLOR, |triangle| {
+                colors[self.indices[triangle * 3] as usize]
+            })
+    }
+
+    /// Returns the positions of the corners of the given triangle.
+    pub fn triangle_positions(&self, triangle: [u32; 3]) -> [[f32; 3]; 3] {
+        [
+            self.positions[triangle[0] as usize],
+            self.positions[triangle[1] as usize],
+            self.positions[triangle[2] as usize],
+        ]
+    }
+}
+
+/// Returns the unit normal of the triangle with the given counter-clockwise
+/// vertices, or the zero vector if the triangle is degenerate.
+pub fn face_normal(vertices: &[[f32; 3]; 3]) -> [f32; 3] {
+    let a = Vector3::from(vertices[0]);
+    let b = Vector3::from(vertices[1]);
+    let c = Vector3::from(vertices[2]);
+    let normal = (b - a).cross(c - a);
+    if normal.magnitude2() > 0.0 {
+        normal.normalize().into()
+    } else {
+        [0.0, 0.0, 0.0]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn flat_triangles_get_their_own_vertices() {
+        let mut part = Part::new();
+        let body = part.add_body("Mesh");
+        let vertices = [[0.0, 0.0, 0.0], [2.0, 0.0, 0.0], [0.0, 2.0, 0.0]];
+        part.add_flat_triangle(vertices, face_normal(&vertices), body);
+        part.add_flat_triangle(vertices, face_normal(&vertices), body);
+        assert_eq!(part.triangle_count(), 2);
+        assert_eq!(part.positions.len(), 6);
+        assert_eq!(part.triangles().nth(1), Some([3, 4, 5]));
+        assert_eq!(part.normals, vec![[0.0, 0.0, 1.0]; 6]);
+    }
+
+    #[test]
+    fn degenerate_triangles_have_no_normal() {
+        let vertices = [[0.0, 0.0, 0.0], [1.0, 1.0, 1.0], [2.0, 2.0, 2.0]];
+        assert_eq!(face_normal(&vertices), [0.0, 0.0, 0.0]);
+    }
+
+    #[test]
+    fn parts_far_from_zero_keep_their_detail() {
+        let mut part = Part::new();
+        part.place_origin([1.0, 2.0, 3.0]);
+        assert_eq!(part.origin, [0.0; 3]);
+
+        let far = [1_000_000.125, 2.5, -3.0];
+        part.place_origin(far);
+        assert_eq!(part.origin, [1_000_000.0, 3.0, -3.0]);
+        // Only the first far position places the origin.
+        part.place_origin([-5e6, 0.0, 0.0]);
+        assert_eq!(part.origin, [1_000_000.0, 3.0, -3.0]);
+
+        let vertex = part.add_vertex(part.local_position(far), [0.0, 0.0, 1.0]);
+        assert_eq!(part.positions[vertex as usize], [0.125, -0.5, 0.0]);
+        assert_eq!(part.absolute_position(vertex), far);
+    }
+
+    #[test]
+    fn recentering_moves_the_origin_into_the_positions() {
+        let mut part = Part::new();
+        part.place_origin([20_000.0, 0.0, 0.0]);
+        part.add_vertex(part.local_position([20_001.0, 0.0, 0.0]), [0.0; 3]);
+        part.add_vertex(part.local_position([20_003.0, 2.0, 0.0]), [0.0; 3]);
+        part.recenter();
+        assert_eq!(part.origin, [0.0; 3]);
+        assert_eq!(part.positions, vec![[-1.0, -1.0, 0.0], [1.0, 1.0, 0.0]]);
+    }
+}
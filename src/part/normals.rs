@@ -0,0 +1,192 @@
+use super::{face_normal, Part};
+use cgmath::{Deg, InnerSpace, Rad, Vector3};
+
+/// How the normals stored in an input file compare with the winding of its
+/// triangles.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct NormalCheck {
+    /// Triangles whose stored normal is zero.
+    pub missing: usize,
+    /// Triangles whose stored normal points away from the side that their
+    /// vertices wind counter-clockwise around.
+    pub disagreeing: usize,
+}
+
+impl Part {
+    /// Compares the normals of each triangle's vertices, which are the facet
+    /// normals for formats like STL, with the normal given by its winding.
+    /// Degenerate triangles have no winding normal and aren't counted.
+    pub fn check_stored_normals(&self) -> NormalCheck {
+        let mut check = NormalCheck::default();
+        for triangle in self.triangles() {
+            let winding = Vector3::from(face_normal(&self.triangle_positions(triangle)));
+            if winding.magnitude2() == 0.0 {
+                continue;
+            }
+            let stored = triangle
+                .iter()
+                .map(|&i| Vector3::from(self.normals[i as usize]))
+                .fold(Vector3::new(0.0, 0.0, 0.0), |sum, normal| sum + normal);
+            if stored.magnitude2() == 0.0 {
+                check.missing += 1;
+            } else if stored.dot(winding) <= 0.0 {
+                check.disagreeing += 1;
+            }
+        }
+        check
+    }
+
+    /// Gives each vertex the average normal of the triangles around it, so
+    /// that curved surfaces are shaded smoothly. Triangles only share a normal
+    /// where the angle between their faces is below `crease_angle`, so sharp
+    /// edges stay crisp, and vertices are split where they don't.
+    ///
+    /// Triangles only count as neighbors if they share vertices, so vertices
+    /// should be welded first.
+    pub fn smooth_normals(&mut self, crease_angle: Deg<f32>) {
+        let min_cos = Rad::from(crease_angle).0.cos();
+
+        // The cross product is proportional to the area of the triangle, so
+        // summing them weights each face by its area.
+        let face_normals = self
+            .triangles()
+            .map(|triangle| {
+                let [a, b, c] = self.triangle_positions(triangle);
+                let (a, b, c) = (Vector3::from(a), Vector3::from(b), Vector3::from(c));
+                (b - a).cross(c - a)
+            })
+            .collect::<Vec<_>>();
+        let unit_normals = face_normals
+            .iter()
+            .map(|&normal| {
+                if normal.magnitude2() > 0.0 {
+                    normal.normalize()
+                } else {
+                    normal
+                }
+            })
+            .collect::<Vec<_>>();
+
+        let mut vertex_triangles = vec![Vec::new(); self.positions.len()];
+        for (triangle, corners) in self.triangles().enumerate() {
+            for &vertex in corners.iter() {
+                vertex_triangles[vertex as usize].push(triangle);
+            }
+        }
+
+        let mut smoothed = Part {
+            colors: self.colors.as_ref().map(|_| Vec::new()),
+            triangle_bodies: std::mem::take(&mut self.triangle_bodies),
+            bodies: std::mem::take(&mut self.bodies),
+            unit: self.unit,
+            origin: self.origin,
+            ..Part::new()
+        };
+        smoothed.indices = vec![0; self.indices.len()];
+
+        for (vertex, triangles) in vertex_triangles.iter().enumerate() {
+            // The normals given to this vertex so far, and the index of the
+            // new vertex that has each.
+            let mut split: Vec<([f32; 3], u32)> = Vec::new();
+
+            for &triangle in triangles.iter() {
+                let sum = triangles
+                    .iter()
+                    // A triangle's own normal may not quite match itself
+                    // after rounding, so it's always included.
+                    .filter(|&&other| {
+                        other == triangle
+                            || unit_normals[triangle].dot(unit_normals[other]) >= min_cos
+                    })
+                    .map(|&other| face_normals[other])
+                    .fold(Vector3::new(0.0, 0.0, 0.0), |sum, normal| sum + normal);
+                // A degenerate triangle has no face to take a normal from.
+                let normal = if sum.magnitude2() > 0.0 {
+                    sum.normalize().into()
+                } else {
+                    self.normals[vertex]
+                };
+
+                let index = match split.iter().find(|(existing, _)| *existing == normal) {
+                    Some(&(_, index)) => index,
+                    None => {
+                        let position = self.positions[vertex];
+                        let index = match &self.colors {
+                            Some(colors) => {
+                                smoothed.add_colored_vertex(position, normal, colors[vertex])
+                            }
+                            None => smoothed.add_vertex(position, normal),
+                        };
+                        split.push((normal, index));
+                        index
+                    }
+                };
+
+                for corner in 0..3 {
+                    if self.indices[triangle * 3 + corner] as usize == vertex {
+                        smoothed.indices[triangle * 3 + corner] = index;
+                    }
+                }
+            }
+        }
+
+        *self = smoothed;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::part::face_normal;
+
+    /// Two triangles that meet along an edge, folded by about eight degrees.
+    fn shallow_fold() -> Part {
+        let mut part = Part::new();
+        let body = part.add_body("Fold");
+        let first = [[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]];
+        let second = [[1.0, 0.0, 0.0], [1.0, 1.0, 0.1], [0.0, 1.0, 0.0]];
+        part.add_flat_triangle(first, face_normal(&first), body);
+        part.add_flat_triangle(second, face_normal(&second), body);
+        part.weld_vertices(1e-4);
+        part
+    }
+
+    #[test]
+    fn stored_normals_are_checked_against_the_winding() {
+        let mut part = Part::new();
+        let body = part.add_body("Facets");
+        let vertices = [[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]];
+        part.add_flat_triangle(vertices, [0.0, 0.0, 1.0], body);
+        part.add_flat_triangle(vertices, [0.0, 0.0, -1.0], body);
+        part.add_flat_triangle(vertices, [0.0, 0.0, 0.0], body);
+        let check = part.check_stored_normals();
+        assert_eq!((check.missing, check.disagreeing), (1, 1));
+    }
+
+    #[test]
+    fn edges_below_the_crease_angle_are_smoothed() {
+        let mut part = shallow_fold();
+        part.smooth_normals(Deg(30.0));
+        assert_eq!(part.positions.len(), 4);
+        let [a, b, _] = part.triangle_positions([0, 1, 2]);
+        assert_eq!([a, b], [[0.0, 0.0, 0.0], [1.0, 0.0, 0.0]]);
+        // The shared corners lean toward the folded triangle.
+        assert!(part.normals[1][0] < 0.0 && part.normals[1][2] < 1.0);
+        assert_eq!(part.normals[0], [0.0, 0.0, 1.0]);
+    }
+
+    #[test]
+    fn edges_above_the_crease_angle_stay_sharp() {
+        for &angle in &[5.0, 0.0] {
+            let mut part = shallow_fold();
+            part.smooth_normals(Deg(angle));
+            assert_eq!(part.positions.len(), 6);
+            for triangle in part.triangles() {
+                let normal = face_normal(&part.triangle_positions(triangle));
+                for &vertex in triangle.iter() {
+                    assert_eq!(part.normals[vertex as usize], normal);
+                }
+            }
+        }
+    }
+}
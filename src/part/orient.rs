@@ -0,0 +1,154 @@
+use super::edges::{edge_key, triangle_edges};
+use super::Part;
+use cgmath::{InnerSpace, Vector3};
+use std::collections::VecDeque;
+
+impl Part {
+    /// Flips triangles so that every connected piece of the part winds
+    /// consistently, with its faces pointing outward, and returns the
+    /// triangles that were flipped. This relies on the winding alone, so it fixes
+    /// parts whose stored normals are missing or wrong.
+    ///
+    /// Triangles are only connected if they share vertices, so vertices should
+    /// be welded first.
+    pub fn orient_outward(&mut self) -> Vec<usize> {
+        let edges = self.edge_map();
+        let mut flipped = vec![false; self.triangle_count()];
+        let mut visited = vec![false; self.triangle_count()];
+        let mut flips = Vec::new();
+
+        for start in 0..self.triangle_count() {
+            if visited[start] {
+                continue;
+            }
+
+            // Walk the connected piece, flipping each neighbor that runs along
+            // a shared edge in the same direction as the triangle it was
+            // reached from. Consistently wound neighbors run along it in
+            // opposite directions.
+            let mut component = vec![start];
+            let mut queue = VecDeque::from(vec![start]);
+            visited[start] = true;
+            while let Some(triangle) = queue.pop_front() {
+                for &(a, b) in triangle_edges(self.oriented(triangle, flipped[triangle])).iter() {
+                    for edge_use in edges[&edge_key(a, b)].iter() {
+                        let neighbor = edge_use.triangle;
+                        if visited[neighbor] {
+                            continue;
+                        }
+                        visited[neighbor] = true;
+                        flipped[neighbor] = edge_use.forward == (a < b);
+                        component.push(neighbor);
+                        queue.push_back(neighbor);
+                    }
+                }
+            }
+
+            // A closed surface that faces inward has a negative volume. Open
+            // surfaces have no inside, but measuring from their own center
+            // still tells which way most of their faces point.
+            let center = component
+                .iter()
+                .flat_map(|&triangle| self.triangle_positions(self.oriented(triangle, false)))
+                .fold(Vector3::new(0.0, 0.0, 0.0), |sum, position| {
+                    sum + Vector3::from(position)
+                })
+                / (component.len() * 3) as f32;
+            let mut volume = 0.0;
+            let mut unsigned_volume = 0.0;
+            let mut agreement = 0.0;
+            for &triangle in component.iter() {
+                let corners = self.oriented(triangle, flipped[triangle]);
+                let [a, b, c] = self.triangle_positions(corners);
+                let (a, b, c) = (
+                    Vector3::from(a) - center,
+                    Vector3::from(b) - center,
+                    Vector3::from(c) - center,
+                );
+                let tetrahedron = a.dot(b.cross(c));
+                volume += tetrahedron;
+                unsigned_volume += tetrahedron.abs();
+
+                let stored = corners
+                    .iter()
+                    .map(|&i| Vector3::from(self.normals[i as usize]))
+                    .fold(Vector3::new(0.0, 0.0, 0.0), |sum, normal| sum + normal);
+                agreement += stored.dot((b - a).cross(c - a));
+            }
+            // A flat surface has no volume either way, so the stored normals
+            // are the only hint of which side is meant to face out.
+            let inward = if volume.abs() > unsigned_volume * 1e-4 {
+                volume < 0.0
+            } else {
+                agreement < 0.0
+            };
+
+            for &triangle in component.iter() {
+                if flipped[triangle] != inward {
+                    self.indices.swap(triangle * 3 + 1, triangle * 3 + 2);
+                    flips.push(triangle);
+                }
+            }
+        }
+
+        flips.sort_unstable();
+        flips
+    }
+
+    /// Returns the corners of a triangle, with the last two swapped if it's
+    /// to be flipped.
+    fn oriented(&self, triangle: usize, flip: bool) -> [u32; 3] {
+        let i = &self.indices[triangle * 3..triangle * 3 + 3];
+        if flip {
+            [i[0], i[2], i[1]]
+        } else {
+            [i[0], i[1], i[2]]
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A tetrahedron with its vertices shared between the given faces.
+    fn tetrahedron(faces: &[[u32; 3]]) -> Part {
+        let mut part = Part::new();
+        let body = part.add_body("Tetrahedron");
+        for &position in &[
+            [0.0, 0.0, 0.0],
+            [1.0, 0.0, 0.0],
+            [0.0, 1.0, 0.0],
+            [0.0, 0.0, 1.0],
+        ] {
+            part.add_vertex(position, [0.0, 0.0, 0.0]);
+        }
+        for &face in faces {
+            part.add_triangle(face, body);
+        }
+        part
+    }
+
+    const OUTWARD: [[u32; 3]; 4] = [[0, 2, 1], [0, 1, 3], [0, 3, 2], [1, 2, 3]];
+
+    #[test]
+    fn a_flipped_face_is_turned_back() {
+        let mut part = tetrahedron(&[[0, 2, 1], [0, 1, 3], [0, 3, 2], [1, 3, 2]]);
+        assert_eq!(part.orient_outward(), [3]);
+        assert_eq!(part.triangles().collect::<Vec<_>>(), OUTWARD);
+    }
+
+    #[test]
+    fn an_inside_out_piece_is_turned_outward() {
+        let inward = OUTWARD
+            .iter()
+            .map(|&[a, b, c]| [a, c, b])
+            .collect::<Vec<_>>();
+        let mut part = tetrahedron(&inward);
+        assert_eq!(part.orient_outward(), [0, 1, 2, 3]);
+        assert_eq!(part.triangles().collect::<Vec<_>>(), OUTWARD);
+
+        let mut part = tetrahedron(&OUTWARD);
+        assert!(part.orient_outward().is_empty());
+    }
+}
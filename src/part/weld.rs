@@ -0,0 +1,123 @@
+use super::Part;
+use cgmath::{MetricSpace, Point3};
+use std::collections::HashMap;
+
+impl Part {
+    /// Merges vertices that are within `tolerance` of each other and have the
+    /// same color, so that triangles which meet share their corners. The
+    /// normals of merged vertices are meaningless until they are recomputed,
+    /// for example with `smooth_normals`.
+    pub fn weld_vertices(&mut self, tolerance: f32) {
+        // Vertices are bucketed into cells the size of the tolerance, so each
+        // vertex only has to be compared with the vertices in neighboring
+        // cells.
+        let cell_size = tolerance.max(f32::EPSILON);
+        let cell = |position: [f32; 3]| -> [i64; 3] {
+            [
+                (position[0] / cell_size).floor() as i64,
+                (position[1] / cell_size).floor() as i64,
+                (position[2] / cell_size).floor() as i64,
+            ]
+        };
+
+        let mut welded = Part {
+            colors: self.colors.as_ref().map(|_| Vec::new()),
+            triangle_bodies: std::mem::take(&mut self.triangle_bodies),
+            bodies: std::mem::take(&mut self.bodies),
+            unit: self.unit,
+            origin: self.origin,
+            ..Part::new()
+        };
+        let mut cells: HashMap<[i64; 3], Vec<u32>> = HashMap::new();
+        let mut remap = Vec::with_capacity(self.positions.len());
+
+        for (i, &position) in self.positions.iter().enumerate() {
+            let color = self.colors.as_ref().map(|colors| colors[i]);
+            let [x, y, z] = cell(position);
+            let mut existing = None;
+            'search: for dx in -1..=1 {
+                for dy in -1..=1 {
+                    for dz in -1..=1 {
+                        let candidates = match cells.get(&[x + dx, y + dy, z + dz]) {
+                            Some(candidates) => candidates,
+                            None => continue,
+                        };
+                        for &candidate in candidates.iter() {
+                            let candidate_position = welded.positions[candidate as usize];
+                            let candidate_color = welded
+                                .colors
+                                .as_ref()
+                                .map(|colors| colors[candidate as usize]);
+                            if candidate_color == color
+                                && Point3::from(candidate_position).distance(Point3::from(position))
+                                    <= tolerance
+                            {
+                                existing = Some(candidate);
+                                break 'search;
+                            }
+                        }
+                    }
+                }
+            }
+
+            let index = existing.unwrap_or_else(|| {
+                let index = match color {
+                    Some(color) => welded.add_colored_vertex(position, self.normals[i], color),
+                    None => welded.add_vertex(position, self.normals[i]),
+                };
+                cells.entry([x, y, z]).or_default().push(index);
+                index
+            });
+            remap.push(index);
+        }
+
+        welded.indices = self.indices.iter().map(|&i| remap[i as usize]).collect();
+        log::info!(
+            "Welded {} vertices into {}",
+            self.positions.len(),
+            welded.positions.len()
+        );
+        *self = welded;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::part::face_normal;
+
+    fn square(offset: f32) -> Part {
+        let mut part = Part::new();
+        let body = part.add_body("Square");
+        let first = [[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]];
+        let second = [[1.0 + offset, 0.0, 0.0], [1.0, 1.0, 0.0], [0.0, 1.0, 0.0]];
+        part.add_flat_triangle(first, face_normal(&first), body);
+        part.add_flat_triangle(second, face_normal(&second), body);
+        part
+    }
+
+    #[test]
+    fn nearby_vertices_are_merged() {
+        let mut part = square(1e-6);
+        part.weld_vertices(1e-4);
+        assert_eq!(part.positions.len(), 4);
+        assert_eq!(part.indices, [0, 1, 2, 1, 3, 2]);
+
+        let mut part = square(1e-3);
+        part.weld_vertices(1e-4);
+        assert_eq!(part.positions.len(), 5);
+    }
+
+    #[test]
+    fn vertices_with_different_colors_are_kept_apart() {
+        let mut part = Part::new();
+        let body = part.add_body("Square");
+        let first = [[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]];
+        let second = [[1.0, 0.0, 0.0], [1.0, 1.0, 0.0], [0.0, 1.0, 0.0]];
+        let normal = face_normal(&first);
+        part.add_colored_flat_triangle(first, normal, [1.0, 0.0, 0.0], body);
+        part.add_colored_flat_triangle(second, normal, [0.0, 0.0, 1.0], body);
+        part.weld_vertices(1e-4);
+        assert_eq!(part.positions.len(), 6);
+    }
+}
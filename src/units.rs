@@ -0,0 +1,89 @@
+use std::fmt;
+
+/// A unit of length that a part may have been modelled in. Parts are converted
+/// to millimetres once they are loaded.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Unit {
+    Micrometre,
+    Millimetre,
+    Centimetre,
+    Metre,
+    Inch,
+    Foot,
+}
+
+impl Unit {
+    /// The names accepted by `from_name`.
+    pub const NAMES: [&'static str; 6] = ["um", "mm", "cm", "m", "in", "ft"];
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "um" => Some(Unit::Micrometre),
+            "mm" => Some(Unit::Millimetre),
+            "cm" => Some(Unit::Centimetre),
+            "m" => Some(Unit::Metre),
+            "in" => Some(Unit::Inch),
+            "ft" => Some(Unit::Foot),
+            _ => None,
+        }
+    }
+
+    /// The number of millimetres in one of this unit.
+    pub fn millimetres(&self) -> f32 {
+        match self {
+            Unit::Micrometre => 0.001,
+            Unit::Millimetre => 1.0,
+            Unit::Centimetre => 10.0,
+            Unit::Metre => 1000.0,
+            Unit::Inch => 25.4,
+            Unit::Foot => 304.8,
+        }
+    }
+
+    /// Guesses the unit of a part whose file doesn't say from the largest of
+    /// its extents. A part less than one unit across is more likely to be in
+    /// metres than to be smaller than a millimetre. Inches and centimetres
+    /// can't be told apart from millimetres by size.
+    pub fn guess(largest_extent: f32) -> Self {
+        if largest_extent > 0.0 && largest_extent < 1.0 {
+            Unit::Metre
+        } else {
+            Unit::Millimetre
+        }
+    }
+}
+
+impl fmt::Display for Unit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Unit::Micrometre => "micrometres",
+            Unit::Millimetre => "millimetres",
+            Unit::Centimetre => "centimetres",
+            Unit::Metre => "metres",
+            Unit::Inch => "inches",
+            Unit::Foot => "feet",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_name_is_a_unit() {
+        for name in Unit::NAMES.iter() {
+            assert!(Unit::from_name(name).is_some());
+        }
+        assert_eq!(Unit::from_name("IN"), Some(Unit::Inch));
+        assert_eq!(Unit::from_name("yd"), None);
+    }
+
+    #[test]
+    fn only_tiny_parts_are_guessed_to_be_in_metres() {
+        assert_eq!(Unit::guess(0.25), Unit::Metre);
+        assert_eq!(Unit::guess(2.0), Unit::Millimetre);
+        assert_eq!(Unit::guess(0.0), Unit::Millimetre);
+    }
+}
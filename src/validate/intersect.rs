@@ -0,0 +1,291 @@
+use crate::part::Part;
+use cgmath::{InnerSpace, Vector3};
+use std::collections::{HashMap, HashSet};
+
+/// Crossings this close to the edge of a triangle, as a fraction of its size,
+/// are treated as touching rather than intersecting.
+const TOUCHING_MARGIN: f64 = 1e-9;
+
+/// The grid used to find nearby triangles is at most this many cells across,
+/// so that a few large triangles don't each cover a huge number of cells.
+const MAX_GRID_CELLS: f64 = 64.0;
+
+type Triangle = [Vector3<f64>; 3];
+
+/// Returns the pairs of triangles that pass through each other. Neighbors that
+/// share a vertex, and the given degenerate triangles, are left out.
+pub fn self_intersections(part: &Part, degenerate: &[usize]) -> Vec<[usize; 2]> {
+    let skip = degenerate.iter().copied().collect::<HashSet<_>>();
+    let triangles = part
+        .triangles()
+        .map(|triangle| {
+            let [a, b, c] = part.triangle_positions(triangle);
+            [to_f64(a), to_f64(b), to_f64(c)]
+        })
+        .collect::<Vec<Triangle>>();
+    if triangles.is_empty() {
+        return Vec::new();
+    }
+
+    let bounds = triangles.iter().map(triangle_bounds).collect::<Vec<_>>();
+    let (part_min, part_max) = bounds.iter().fold(bounds[0], |(min, max), &(a, b)| {
+        (component_min(min, a), component_max(max, b))
+    });
+
+    // Cells about the size of a typical triangle keep the number of
+    // candidate pairs in each cell small.
+    let mean_size = bounds
+        .iter()
+        .map(|(min, max)| {
+            let size = max - min;
+            size.x.max(size.y).max(size.z)
+        })
+        .sum::<f64>()
+        / bounds.len() as f64;
+    let part_size = part_max - part_min;
+    let cell_size = mean_size
+        .max(part_size.x.max(part_size.y).max(part_size.z) / MAX_GRID_CELLS)
+        .max(f64::MIN_POSITIVE);
+    let cell = |position: Vector3<f64>| -> [i64; 3] {
+        let offset = (position - part_min) / cell_size;
+        [offset.x as i64, offset.y as i64, offset.z as i64]
+    };
+
+    let mut cells: HashMap<[i64; 3], Vec<usize>> = HashMap::new();
+    for (i, &(min, max)) in bounds.iter().enumerate() {
+        if skip.contains(&i) {
+            continue;
+        }
+        let (low, high) = (cell(min), cell(max));
+        for x in low[0]..=high[0] {
+            for y in low[1]..=high[1] {
+                for z in low[2]..=high[2] {
+                    cells.entry([x, y, z]).or_default().push(i);
+                }
+            }
+        }
+    }
+
+    let corners = part.triangles().collect::<Vec<_>>();
+    let mut tested = HashSet::new();
+    let mut intersections = Vec::new();
+    for candidates in cells.values() {
+        for (n, &i) in candidates.iter().enumerate() {
+            for &j in candidates[n + 1..].iter() {
+                let pair = [i.min(j), i.max(j)];
+                if corners[i].iter().any(|vertex| corners[j].contains(vertex))
+                    || !bounds_overlap(bounds[i], bounds[j])
+                    || !tested.insert(pair)
+                {
+                    continue;
+                }
+                if triangles_intersect(&triangles[i], &triangles[j]) {
+                    intersections.push(pair);
+                }
+            }
+        }
+    }
+
+    intersections.sort_unstable();
+    intersections
+}
+
+/// Two triangles that aren't coplanar intersect if an edge of one passes
+/// through the other. Coplanar triangles intersect if they overlap.
+fn triangles_intersect(a: &Triangle, b: &Triangle) -> bool {
+    if coplanar(a, b) {
+        return coplanar_triangles_overlap(a, b);
+    }
+    let crosses = |triangle: &Triangle, other: &Triangle| {
+        (0..3).any(|i| segment_crosses(triangle[i], triangle[(i + 1) % 3], other))
+    };
+    crosses(a, b) || crosses(b, a)
+}
+
+/// Tests whether the segment from `p` to `q` passes through the interior of
+/// the triangle, with the Möller–Trumbore algorithm.
+fn segment_crosses(p: Vector3<f64>, q: Vector3<f64>, triangle: &Triangle) -> bool {
+    let direction = q - p;
+    let edge1 = triangle[1] - triangle[0];
+    let edge2 = triangle[2] - triangle[0];
+    let h = direction.cross(edge2);
+    let determinant = edge1.dot(h);
+    // The segment is parallel to the triangle.
+    if determinant.abs()
+        <= TOUCHING_MARGIN * edge1.magnitude() * edge2.magnitude() * direction.magnitude()
+    {
+        return false;
+    }
+
+    let inverse = 1.0 / determinant;
+    let s = p - triangle[0];
+    let u = inverse * s.dot(h);
+    if u <= TOUCHING_MARGIN || u >= 1.0 - TOUCHING_MARGIN {
+        return false;
+    }
+    let r = s.cross(edge1);
+    let v = inverse * direction.dot(r);
+    if v <= TOUCHING_MARGIN || u + v >= 1.0 - TOUCHING_MARGIN {
+        return false;
+    }
+    let t = inverse * edge2.dot(r);
+    t > TOUCHING_MARGIN && t < 1.0 - TOUCHING_MARGIN
+}
+
+/// Tests whether both triangles lie in the same plane.
+fn coplanar(a: &Triangle, b: &Triangle) -> bool {
+    let normal = (a[1] - a[0]).cross(a[2] - a[0]);
+    let size = a
+        .iter()
+        .chain(b.iter())
+        .map(|&corner| (corner - a[0]).magnitude())
+        .fold(0.0, f64::max);
+    let margin = TOUCHING_MARGIN * normal.magnitude() * size;
+    b.iter()
+        .all(|&corner| (corner - a[0]).dot(normal).abs() <= margin)
+}
+
+/// Tests whether the interiors of two coplanar triangles overlap. They're
+/// projected onto the axis plane they're least steep to, where they overlap
+/// if their edges cross or one holds a corner or the center of the other.
+fn coplanar_triangles_overlap(a: &Triangle, b: &Triangle) -> bool {
+    let normal = (a[1] - a[0]).cross(a[2] - a[0]);
+    let (i, j) = if normal.x.abs() >= normal.y.abs() && normal.x.abs() >= normal.z.abs() {
+        (1, 2)
+    } else if normal.y.abs() >= normal.z.abs() {
+        (0, 2)
+    } else {
+        (0, 1)
+    };
+    let project = |triangle: &Triangle| -> [[f64; 2]; 3] {
+        let point = |corner: Vector3<f64>| [corner[i], corner[j]];
+        [point(triangle[0]), point(triangle[1]), point(triangle[2])]
+    };
+    let (a, b) = (project(a), project(b));
+
+    let edges_cross =
+        (0..3).any(|m| (0..3).any(|n| segments_cross(a[m], a[(m + 1) % 3], b[n], b[(n + 1) % 3])));
+    let holds_point = |triangle: &[[f64; 2]; 3], other: &[[f64; 2]; 3]| {
+        let center = [
+            (other[0][0] + other[1][0] + other[2][0]) / 3.0,
+            (other[0][1] + other[1][1] + other[2][1]) / 3.0,
+        ];
+        other
+            .iter()
+            .chain(std::iter::once(&center))
+            .any(|&point| point_in_triangle(point, triangle))
+    };
+    edges_cross || holds_point(&a, &b) || holds_point(&b, &a)
+}
+
+fn cross_2d(a: [f64; 2], b: [f64; 2]) -> f64 {
+    a[0] * b[1] - a[1] * b[0]
+}
+
+/// Tests whether the segments from `p` to `q` and from `r` to `s` cross away
+/// from their ends.
+fn segments_cross(p: [f64; 2], q: [f64; 2], r: [f64; 2], s: [f64; 2]) -> bool {
+    let d1 = [q[0] - p[0], q[1] - p[1]];
+    let d2 = [s[0] - r[0], s[1] - r[1]];
+    let offset = [r[0] - p[0], r[1] - p[1]];
+    let denominator = cross_2d(d1, d2);
+    // Parallel segments only touch.
+    let lengths = (d1[0].hypot(d1[1])) * (d2[0].hypot(d2[1]));
+    if denominator.abs() <= TOUCHING_MARGIN * lengths {
+        return false;
+    }
+    let t = cross_2d(offset, d2) / denominator;
+    let u = cross_2d(offset, d1) / denominator;
+    t > TOUCHING_MARGIN
+        && t < 1.0 - TOUCHING_MARGIN
+        && u > TOUCHING_MARGIN
+        && u < 1.0 - TOUCHING_MARGIN
+}
+
+/// Tests whether the point is inside the triangle and not on its edges.
+fn point_in_triangle(point: [f64; 2], triangle: &[[f64; 2]; 3]) -> bool {
+    let area = cross_2d(
+        [
+            triangle[1][0] - triangle[0][0],
+            triangle[1][1] - triangle[0][1],
+        ],
+        [
+            triangle[2][0] - triangle[0][0],
+            triangle[2][1] - triangle[0][1],
+        ],
+    );
+    if area == 0.0 {
+        return false;
+    }
+    (0..3).all(|k| {
+        let from = triangle[k];
+        let to = triangle[(k + 1) % 3];
+        let edge = [to[0] - from[0], to[1] - from[1]];
+        let offset = [point[0] - from[0], point[1] - from[1]];
+        cross_2d(edge, offset) / area > TOUCHING_MARGIN
+    })
+}
+
+fn to_f64(position: [f32; 3]) -> Vector3<f64> {
+    Vector3::new(position[0] as f64, position[1] as f64, position[2] as f64)
+}
+
+fn triangle_bounds(triangle: &Triangle) -> (Vector3<f64>, Vector3<f64>) {
+    (
+        component_min(component_min(triangle[0], triangle[1]), triangle[2]),
+        component_max(component_max(triangle[0], triangle[1]), triangle[2]),
+    )
+}
+
+fn bounds_overlap(
+    (a_min, a_max): (Vector3<f64>, Vector3<f64>),
+    (b_min, b_max): (Vector3<f64>, Vector3<f64>),
+) -> bool {
+    a_min.x <= b_max.x
+        && b_min.x <= a_max.x
+        && a_min.y <= b_max.y
+        && b_min.y <= a_max.y
+        && a_min.z <= b_max.z
+        && b_min.z <= a_max.z
+}
+
+fn component_min(a: Vector3<f64>, b: Vector3<f64>) -> Vector3<f64> {
+    Vector3::new(a.x.min(b.x), a.y.min(b.y), a.z.min(b.z))
+}
+
+fn component_max(a: Vector3<f64>, b: Vector3<f64>) -> Vector3<f64> {
+    Vector3::new(a.x.max(b.x), a.y.max(b.y), a.z.max(b.z))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn part(triangles: &[[[f32; 3]; 3]]) -> Part {
+        let mut part = Part::new();
+        let body = part.add_body("Part");
+        for &triangle in triangles {
+            part.add_flat_triangle(triangle, [0.0, 0.0, 0.0], body);
+        }
+        part
+    }
+
+    #[test]
+    fn crossing_triangles_intersect() {
+        let flat = [[0.0, 0.0, 0.0], [2.0, 0.0, 0.0], [0.0, 2.0, 0.0]];
+        let upright = [[0.5, 0.5, -1.0], [0.5, 0.5, 1.0], [1.0, 0.0, 0.0]];
+        let above = [[0.5, 0.5, 1.0], [0.5, 0.5, 2.0], [1.0, 0.0, 1.0]];
+        let part = part(&[flat, upright, above]);
+        assert_eq!(self_intersections(&part, &[]), [[0, 1]]);
+    }
+
+    #[test]
+    fn overlapping_coplanar_triangles_intersect() {
+        let first = [[0.0, 0.0, 0.0], [2.0, 0.0, 0.0], [0.0, 2.0, 0.0]];
+        let second = [[0.5, 0.5, 0.0], [3.0, 0.5, 0.0], [0.5, 3.0, 0.0]];
+        let beside = [[5.0, 0.0, 0.0], [6.0, 0.0, 0.0], [5.0, 1.0, 0.0]];
+        let part = part(&[first, second, beside]);
+        assert_eq!(self_intersections(&part, &[]), [[0, 1]]);
+        assert!(self_intersections(&part, &[1]).is_empty());
+    }
+}
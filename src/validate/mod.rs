@@ -0,0 +1,499 @@
+mod intersect;
+
+use crate::part::edges::{edge_key, EdgeMap};
+use crate::part::{color::palette_color, face_normal, Part};
+use cgmath::{InnerSpace, Vector3};
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+/// How many problems of each kind are listed in the text report. The JSON
+/// report lists all of them.
+const TEXT_REPORT_LIMIT: usize = 10;
+
+/// A triangle is degenerate if the sine of its largest angle is below this, so
+/// slivers with no real area are caught along with collapsed triangles.
+const DEGENERATE_SINE: f32 = 1e-6;
+
+/// The color of triangles without problems in the highlighted part.
+const OK_COLOR: [f32; 3] = [0.5, 0.5, 0.5];
+
+#[derive(Clone, Copy, Debug, Serialize)]
+pub struct Edge {
+    pub from: [f64; 3],
+    pub to: [f64; 3],
+    /// The triangles that have this edge as a side.
+    pub triangles: usize,
+}
+
+/// A loop of boundary edges around a hole in the surface.
+#[derive(Clone, Debug, Serialize)]
+pub struct Hole {
+    pub vertices: Vec<[f64; 3]>,
+    /// Whether the boundary edges close into a loop. They may not where the
+    /// surface is non-manifold.
+    pub closed: bool,
+}
+
+/// The problems that would stop a part from printing properly. Triangles are
+/// referred to by their position in the input file, starting from zero.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct Report {
+    pub triangles: usize,
+    pub vertices: usize,
+    /// Edges with a triangle on only one side.
+    pub boundary_edges: Vec<Edge>,
+    pub holes: Vec<Hole>,
+    /// Edges shared by more than two triangles.
+    pub non_manifold_edges: Vec<Edge>,
+    /// Vertices where separate fans of triangles meet at a single point.
+    pub non_manifold_vertices: Vec<[f64; 3]>,
+    pub degenerate_triangles: Vec<usize>,
+    /// Pairs of triangles with the same three vertices.
+    pub duplicate_triangles: Vec<[usize; 2]>,
+    /// Edges whose two triangles wind along it in the same direction, so one
+    /// of them faces the wrong way.
+    pub inconsistent_edges: Vec<Edge>,
+    /// Triangles that would have to be flipped for every piece of the part to
+    /// wind consistently and face outward.
+    pub flipped_triangles: Vec<usize>,
+    pub self_intersections: Vec<[usize; 2]>,
+}
+
+impl Report {
+    /// Checks a part for problems. Triangles only count as neighbors if they
+    /// share vertices, so vertices should be welded first.
+    pub fn new(part: &Part) -> Self {
+        let edges = part.edge_map();
+        let edge = |(a, b): (u32, u32), triangles: usize| Edge {
+            from: part.absolute_position(a),
+            to: part.absolute_position(b),
+            triangles,
+        };
+
+        let mut report = Report {
+            triangles: part.triangle_count(),
+            vertices: part.positions.len(),
+            ..Default::default()
+        };
+
+        let mut keys = edges.keys().copied().collect::<Vec<_>>();
+        keys.sort_unstable();
+        for key in keys {
+            let uses = &edges[&key];
+            match uses.len() {
+                1 => report.boundary_edges.push(edge(key, 1)),
+                2 if uses[0].forward == uses[1].forward => {
+                    report.inconsistent_edges.push(edge(key, 2))
+                }
+                2 => {}
+                count => report.non_manifold_edges.push(edge(key, count)),
+            }
+        }
+
+        report.holes = find_holes(part, &edges);
+        report.non_manifold_vertices = non_manifold_vertices(part)
+            .into_iter()
+            .map(|vertex| part.absolute_position(vertex))
+            .collect();
+        report.degenerate_triangles = part
+            .triangles()
+            .enumerate()
+            .filter(|&(_, triangle)| is_degenerate(part, triangle))
+            .map(|(i, _)| i)
+            .collect();
+        report.duplicate_triangles = duplicate_triangles(part);
+        report.flipped_triangles = part.clone().orient_outward();
+        report.self_intersections =
+            intersect::self_intersections(part, &report.degenerate_triangles);
+
+        report
+    }
+
+    pub fn problem_count(&self) -> usize {
+        self.boundary_edges.len()
+            + self.non_manifold_edges.len()
+            + self.non_manifold_vertices.len()
+            + self.degenerate_triangles.len()
+            + self.duplicate_triangles.len()
+            // Inconsistent edges are where the flipped triangles meet their
+            // neighbors, so they'd count the same problem twice.
+            + self.flipped_triangles.len()
+            + self.self_intersections.len()
+    }
+
+    /// Returns a copy of the part with a body for each kind of problem found,
+    /// so that a screenshot with a legend shows where the problems are.
+    /// Problem edges are shown by highlighting the triangles beside them.
+    /// Every triangle is oriented outward so that flipped ones aren't culled.
+    pub fn highlight(&self, part: &Part) -> Part {
+        let mut boundary = Vec::new();
+        let mut non_manifold = Vec::new();
+        for uses in part.edge_map().values() {
+            let triangles = match uses.len() {
+                1 => &mut boundary,
+                2 => continue,
+                _ => &mut non_manifold,
+            };
+            triangles.extend(uses.iter().map(|edge_use| edge_use.triangle));
+        }
+
+        // Later kinds of problem take precedence when a triangle has several.
+        let categories = [
+            ("Boundary edges", boundary),
+            ("Non-manifold edges", non_manifold),
+            ("Flipped triangles", self.flipped_triangles.clone()),
+            (
+                "Degenerate or duplicate triangles",
+                self.degenerate_triangles
+                    .iter()
+                    .copied()
+                    .chain(
+                        self.duplicate_triangles
+                            .iter()
+                            .flat_map(|pair| pair.to_vec()),
+                    )
+                    .collect(),
+            ),
+            (
+                "Self-intersections",
+                self.self_intersections
+                    .iter()
+                    .flat_map(|pair| pair.to_vec())
+                    .collect(),
+            ),
+        ];
+
+        let mut highlighted = Part {
+            unit: part.unit,
+            origin: part.origin,
+            ..Part::new()
+        };
+        let mut category_of = vec![None; part.triangle_count()];
+        for (i, (name, triangles)) in categories.iter().enumerate() {
+            if triangles.is_empty() {
+                continue;
+            }
+            let body = highlighted.add_body(name);
+            for &triangle in triangles.iter() {
+                category_of[triangle] = Some((body, palette_color(i + 1)));
+            }
+        }
+        let ok = highlighted.add_body("No problems");
+
+        let mut oriented = part.clone();
+        oriented.orient_outward();
+        for (i, triangle) in oriented.triangles().enumerate() {
+            let (body, color) = category_of[i].unwrap_or((ok, OK_COLOR));
+            let vertices = oriented.triangle_positions(triangle);
+            highlighted.add_colored_flat_triangle(vertices, face_normal(&vertices), color, body);
+        }
+        highlighted
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{} triangles, {} vertices",
+            self.triangles, self.vertices
+        )?;
+
+        writeln!(
+            f,
+            "Boundary edges: {} around {} holes",
+            self.boundary_edges.len(),
+            self.holes.len()
+        )?;
+        for (i, hole) in self.holes.iter().take(TEXT_REPORT_LIMIT).enumerate() {
+            let edges = if hole.closed {
+                hole.vertices.len()
+            } else {
+                hole.vertices.len() - 1
+            };
+            writeln!(
+                f,
+                "  Hole {}: {} edges{}, starting at {}",
+                i,
+                edges,
+                if hole.closed { "" } else { ", not closed" },
+                format_position(hole.vertices[0])
+            )?;
+        }
+        write_list(f, "Non-manifold edges", &self.non_manifold_edges, |edge| {
+            format!(
+                "{} to {}, shared by {} triangles",
+                format_position(edge.from),
+                format_position(edge.to),
+                edge.triangles
+            )
+        })?;
+        write_list(
+            f,
+            "Non-manifold vertices",
+            &self.non_manifold_vertices,
+            |&vertex| format_position(vertex),
+        )?;
+        write_list(
+            f,
+            "Degenerate triangles",
+            &self.degenerate_triangles,
+            |triangle| format!("Triangle {}", triangle),
+        )?;
+        write_list(
+            f,
+            "Duplicate triangles",
+            &self.duplicate_triangles,
+            |pair| format!("Triangles {} and {}", pair[0], pair[1]),
+        )?;
+        write_list(
+            f,
+            "Inconsistently oriented edges",
+            &self.inconsistent_edges,
+            |edge| {
+                format!(
+                    "{} to {}",
+                    format_position(edge.from),
+                    format_position(edge.to)
+                )
+            },
+        )?;
+        write_list(
+            f,
+            "Flipped triangles",
+            &self.flipped_triangles,
+            |triangle| format!("Triangle {}", triangle),
+        )?;
+        write_list(
+            f,
+            "Self-intersecting triangle pairs",
+            &self.self_intersections,
+            |pair| format!("Triangles {} and {}", pair[0], pair[1]),
+        )?;
+
+        match self.problem_count() {
+            0 => write!(f, "No problems found"),
+            count => write!(f, "{} problems found", count),
+        }
+    }
+}
+
+fn write_list<T>(
+    f: &mut fmt::Formatter<'_>,
+    title: &str,
+    items: &[T],
+    describe: impl Fn(&T) -> String,
+) -> fmt::Result {
+    writeln!(f, "{}: {}", title, items.len())?;
+    for item in items.iter().take(TEXT_REPORT_LIMIT) {
+        writeln!(f, "  {}", describe(item))?;
+    }
+    if items.len() > TEXT_REPORT_LIMIT {
+        writeln!(f, "  ...and {} more", items.len() - TEXT_REPORT_LIMIT)?;
+    }
+    Ok(())
+}
+
+fn format_position(position: [f64; 3]) -> String {
+    format!("({}, {}, {})", position[0], position[1], position[2])
+}
+
+/// Chains the boundary edges of the part into loops.
+fn find_holes(part: &Part, edges: &EdgeMap) -> Vec<Hole> {
+    let mut boundary: HashMap<u32, Vec<u32>> = HashMap::new();
+    let mut keys = edges
+        .iter()
+        .filter(|(_, uses)| uses.len() == 1)
+        .map(|(&key, _)| key)
+        .collect::<Vec<_>>();
+    keys.sort_unstable();
+    for &(a, b) in keys.iter() {
+        boundary.entry(a).or_default().push(b);
+        boundary.entry(b).or_default().push(a);
+    }
+
+    let mut used = HashSet::new();
+    let mut holes = Vec::new();
+    for &(a, b) in keys.iter() {
+        if used.contains(&(a, b)) {
+            continue;
+        }
+        used.insert((a, b));
+
+        let mut vertices = vec![a, b];
+        let mut closed = false;
+        loop {
+            let current = *vertices.last().unwrap();
+            let next = boundary[&current]
+                .iter()
+                .copied()
+                .find(|&next| !used.contains(&edge_key(current, next)));
+            match next {
+                Some(next) => {
+                    used.insert(edge_key(current, next));
+                    if next == a {
+                        closed = true;
+                        break;
+                    }
+                    vertices.push(next);
+                }
+                None => break,
+            }
+        }
+
+        holes.push(Hole {
+            vertices: vertices
+                .iter()
+                .map(|&vertex| part.absolute_position(vertex))
+                .collect(),
+            closed,
+        });
+    }
+    holes
+}
+
+/// Returns the vertices where the triangles around them form more than one
+/// fan, such as where two cones meet at their tips.
+fn non_manifold_vertices(part: &Part) -> Vec<u32> {
+    let mut vertex_triangles = vec![Vec::new(); part.positions.len()];
+    for (triangle, corners) in part.triangles().enumerate() {
+        for &vertex in corners.iter() {
+            vertex_triangles[vertex as usize].push((triangle, corners));
+        }
+    }
+
+    let mut vertices = Vec::new();
+    for (vertex, triangles) in vertex_triangles.iter().enumerate() {
+        if triangles.len() < 2 {
+            continue;
+        }
+
+        // Walk from the first triangle to the others through shared edges
+        // that end at this vertex.
+        let mut reached = vec![false; triangles.len()];
+        let mut stack = vec![0];
+        reached[0] = true;
+        while let Some(i) = stack.pop() {
+            let (_, corners) = triangles[i];
+            for (j, &(_, other)) in triangles.iter().enumerate() {
+                let shares_edge = corners
+                    .iter()
+                    .any(|&c| c != vertex as u32 && other.contains(&c));
+                if !reached[j] && shares_edge {
+                    reached[j] = true;
+                    stack.push(j);
+                }
+            }
+        }
+        if reached.iter().any(|&reached| !reached) {
+            vertices.push(vertex as u32);
+        }
+    }
+    vertices
+}
+
+fn is_degenerate(part: &Part, triangle: [u32; 3]) -> bool {
+    if triangle[0] == triangle[1] || triangle[1] == triangle[2] || triangle[2] == triangle[0] {
+        return true;
+    }
+    let [a, b, c] = part.triangle_positions(triangle);
+    let (a, b, c) = (Vector3::from(a), Vector3::from(b), Vector3::from(c));
+    let longest = (b - a)
+        .magnitude2()
+        .max((c - b).magnitude2())
+        .max((a - c).magnitude2());
+    // |ab x ac| is twice the area, and at most the product of two sides.
+    (b - a).cross(c - a).magnitude() <= DEGENERATE_SINE * longest
+}
+
+/// Returns the pairs of triangles that have the same three vertices, whichever
+/// way they wind.
+fn duplicate_triangles(part: &Part) -> Vec<[usize; 2]> {
+    let mut seen: HashMap<[u32; 3], usize> = HashMap::new();
+    let mut duplicates = Vec::new();
+    for (i, triangle) in part.triangles().enumerate() {
+        let mut key = triangle;
+        key.sort_unstable();
+        match seen.get(&key) {
+            Some(&first) => duplicates.push([first, i]),
+            None => {
+                seen.insert(key, i);
+            }
+        }
+    }
+    duplicates
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TETRAHEDRON: [[u32; 3]; 4] = [[0, 2, 1], [0, 1, 3], [0, 3, 2], [1, 2, 3]];
+
+    fn part(positions: &[[f32; 3]], faces: &[[u32; 3]]) -> Part {
+        let mut part = Part::new();
+        let body = part.add_body("Part");
+        for &position in positions {
+            part.add_vertex(position, [0.0, 0.0, 0.0]);
+        }
+        for &face in faces {
+            part.add_triangle(face, body);
+        }
+        part
+    }
+
+    fn tetrahedron(faces: &[[u32; 3]]) -> Part {
+        let positions = [
+            [0.0, 0.0, 0.0],
+            [1.0, 0.0, 0.0],
+            [0.0, 1.0, 0.0],
+            [0.0, 0.0, 1.0],
+        ];
+        part(&positions, faces)
+    }
+
+    #[test]
+    fn a_closed_tetrahedron_has_no_problems() {
+        let report = Report::new(&tetrahedron(&TETRAHEDRON));
+        assert_eq!((report.triangles, report.vertices), (4, 4));
+        assert_eq!(report.problem_count(), 0);
+    }
+
+    #[test]
+    fn a_missing_face_leaves_a_closed_hole() {
+        let report = Report::new(&tetrahedron(&TETRAHEDRON[..3]));
+        assert_eq!(report.boundary_edges.len(), 3);
+        assert_eq!(report.holes.len(), 1);
+        assert!(report.holes[0].closed);
+        assert_eq!(report.holes[0].vertices.len(), 3);
+        assert_eq!(report.problem_count(), 3);
+    }
+
+    #[test]
+    fn a_flipped_face_is_reported_once() {
+        let report = Report::new(&tetrahedron(&[[0, 2, 1], [0, 1, 3], [0, 3, 2], [1, 3, 2]]));
+        assert_eq!(report.flipped_triangles, [3]);
+        assert_eq!(report.inconsistent_edges.len(), 3);
+        assert_eq!(report.problem_count(), 1);
+    }
+
+    #[test]
+    fn degenerate_and_duplicate_triangles_are_found() {
+        let report = Report::new(&tetrahedron(&[
+            [0, 2, 1],
+            [0, 1, 3],
+            [0, 3, 2],
+            [1, 2, 3],
+            [3, 2, 1],
+        ]));
+        assert_eq!(report.duplicate_triangles, [[3, 4]]);
+        assert_eq!(report.non_manifold_edges.len(), 3);
+
+        let sliver = part(
+            &[[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [2.0, 0.0, 0.0]],
+            &[[0, 1, 2]],
+        );
+        assert_eq!(Report::new(&sliver).degenerate_triangles, [0]);
+    }
+}
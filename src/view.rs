@@ -0,0 +1,287 @@
+use crate::bounding_box::BoundingBox;
+use crate::part::Part;
+use cgmath::{Angle, Deg, InnerSpace, Point3, Vector3};
+use std::ops::Neg;
+
+/// A direction to look at the part from, in the renderer's coordinates. The
+/// renderer rotates the model -90 degrees around the x axis, so the Z-up
+/// coordinates of most parts end up with Y pointing up.
+#[derive(Clone, Copy, Debug)]
+pub struct View {
+    /// Unit vector from the center of the part toward the camera.
+    pub direction: Vector3<f32>,
+    /// Unit vector, perpendicular to the direction, that points up in the
+    /// image.
+    pub up: Vector3<f32>,
+}
+
+impl View {
+    /// Returns the view from the given direction with Y pointing as far up in
+    /// the image as it can. Looking straight down, the back of the part is at
+    /// the top of the image, and looking straight up, the front is.
+    pub fn from_direction(direction: Vector3<f32>) -> Self {
+        let direction = direction.normalize();
+        let world_up = Vector3::unit_y();
+        let up = world_up - direction * direction.dot(world_up);
+        let up = if up.magnitude2() > 1e-6 {
+            up.normalize()
+        } else {
+            Vector3::new(0.0, 0.0, -direction.y.signum())
+        };
+        Self { direction, up }
+    }
+
+    /// The names accepted by `from_name`.
+    pub const NAMES: [&'static str; 8] = [
+        "front", "back", "left", "right", "top", "bottom", "iso", "dimetric",
+    ];
+
+    /// Returns the standard view with the given name. As in most CAD
+    /// programs, the part is taken to have Z up and its front facing -Y. The
+    /// isometric view looks at the front, right and top equally, and the
+    /// dimetric view looks mostly at the front, with depth drawn at half the
+    /// scale of width and height.
+    pub fn from_name(name: &str) -> Option<Self> {
+        let direction = match name.to_ascii_lowercase().as_str() {
+            "front" => [0.0, -1.0, 0.0],
+            "back" => [0.0, 1.0, 0.0],
+            "left" => [-1.0, 0.0, 0.0],
+            "right" => [1.0, 0.0, 0.0],
+            "top" => [0.0, 0.0, 1.0],
+            "bottom" => [0.0, 0.0, -1.0],
+            "iso" => [1.0, -1.0, 1.0],
+            "dimetric" => [1.0, -(7.0f32).sqrt(), 1.0],
+            _ => return None,
+        };
+        Some(View::from_direction(to_render_space(direction)))
+    }
+
+    /// Returns the view from the given angles around the part, which is taken
+    /// to have Z up. An azimuth of zero looks at the front of the part, along
+    /// +Y, and the view turns toward the right side as the azimuth increases.
+    /// The elevation is the angle above the part's horizontal plane.
+    pub fn from_angles(azimuth: Deg<f32>, elevation: Deg<f32>) -> Self {
+        let direction = [
+            azimuth.sin() * elevation.cos(),
+            -azimuth.cos() * elevation.cos(),
+            elevation.sin(),
+        ];
+        View::from_direction(to_render_space(direction))
+    }
+
+    /// Returns the view rolled about its direction, with the up vector turned
+    /// toward the right by the given angle.
+    pub fn rolled(&self, roll: Deg<f32>) -> Self {
+        Self {
+            direction: self.direction,
+            up: self.up * roll.cos() + self.right() * roll.sin(),
+        }
+    }
+
+    /// Returns the unit vector that points right in the image.
+    pub fn right(&self) -> Vector3<f32> {
+        self.up.cross(self.direction)
+    }
+}
+
+/// Returns the directions the automatic view is picked from: each axis in both
+/// directions and the eight isometric diagonals. Earlier views win ties, so
+/// the front comes first and views from above come before views from below.
+fn candidate_views() -> Vec<View> {
+    let mut directions = vec![
+        Vector3::unit_z(),
+        Vector3::unit_x(),
+        Vector3::unit_y(),
+        -Vector3::unit_z(),
+        -Vector3::unit_x(),
+        -Vector3::unit_y(),
+    ];
+    for &y in [1.0, -1.0].iter() {
+        for &(x, z) in [(1.0, 1.0), (-1.0, 1.0), (-1.0, -1.0), (1.0, -1.0)].iter() {
+            directions.push(Vector3::new(x, y, z));
+        }
+    }
+    directions.into_iter().map(View::from_direction).collect()
+}
+
+/// Where the camera is placed, in the renderer's coordinates with the part
+/// centered on the origin.
+#[derive(Clone, Copy, Debug)]
+pub enum Placement {
+    /// Fit the part in the image from the chosen view, rolled by the given
+    /// angle.
+    Framed(ViewChoice, Deg<f32>),
+    /// Look from the eye to the target, with the part's up direction as far
+    /// up in the image as it can be.
+    Explicit {
+        eye: Point3<f32>,
+        target: Point3<f32>,
+    },
+}
+
+/// Where the camera is placed and how it projects the part.
+#[derive(Clone, Copy, Debug)]
+pub struct CameraSettings {
+    pub placement: Placement,
+    /// Whether to draw without perspective, so that parallel edges stay
+    /// parallel.
+    pub orthographic: bool,
+}
+
+/// How the view of the part is chosen.
+#[derive(Clone, Copy, Debug)]
+pub enum ViewChoice {
+    /// Pick the view that shows the most of the part.
+    Auto(ViewStrategy),
+    /// Look at the part from the given view.
+    Fixed(View),
+}
+
+impl ViewChoice {
+    /// Returns the chosen view of the part. The bounding box must be the
+    /// part's.
+    pub fn view(&self, part: &Part, bounding_box: &BoundingBox) -> View {
+        match self {
+            ViewChoice::Auto(strategy) => strategy.pick_view(part, bounding_box),
+            ViewChoice::Fixed(view) => *view,
+        }
+    }
+}
+
+/// How the automatic view is scored.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ViewStrategy {
+    /// The area of the bounding box's silhouette.
+    BoundingBox,
+    /// The area of the part's silhouette.
+    Silhouette,
+    /// The projected area of the part's triangles that face the camera.
+    VisibleSurface,
+}
+
+impl ViewStrategy {
+    /// The names accepted by `from_name`.
+    pub const NAMES: [&'static str; 3] = ["box", "silhouette", "visible"];
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "box" => Some(ViewStrategy::BoundingBox),
+            "silhouette" => Some(ViewStrategy::Silhouette),
+            "visible" => Some(ViewStrategy::VisibleSurface),
+            _ => None,
+        }
+    }
+
+    /// Returns the view of the part that shows the most of it by this
+    /// strategy's measure. The bounding box must be the part's.
+    pub fn pick_view(&self, part: &Part, bounding_box: &BoundingBox) -> View {
+        let views = candidate_views();
+        let scores: Vec<f64> = match self {
+            ViewStrategy::BoundingBox => views
+                .iter()
+                .map(|view| bounding_box.projected_area(view.direction) as f64)
+                .collect(),
+            ViewStrategy::Silhouette | ViewStrategy::VisibleSurface => {
+                self.score_surface(part, &views)
+            }
+        };
+        // Scores summed over many triangles pick up rounding error, so a view
+        // has to be clearly better to beat an earlier one.
+        let mut best = 0;
+        for (index, &score) in scores.iter().enumerate() {
+            if score > scores[best] * (1.0 + 1e-6) {
+                best = index;
+            }
+        }
+        views[best]
+    }
+
+    /// Scores each view by the area of the part's triangles projected onto
+    /// the image.
+    fn score_surface(&self, part: &Part, views: &[View]) -> Vec<f64> {
+        let mut scores = vec![0.0; views.len()];
+        for triangle in part.triangles() {
+            let [a, b, c] = part.triangle_positions(triangle);
+            let (a, b, c) = (to_render_space(a), to_render_space(b), to_render_space(c));
+            // Twice the area, in the direction of the triangle's normal.
+            let area_normal = (b - a).cross(c - a);
+            if !area_normal.magnitude2().is_finite() {
+                continue;
+            }
+            for (score, view) in scores.iter_mut().zip(views) {
+                // Twice the area of the triangle's projection, if it faces the
+                // camera.
+                let facing = area_normal.dot(view.direction) as f64;
+                *score += match self {
+                    // A closed surface covers its silhouette twice, once
+                    // facing the camera and once facing away.
+                    ViewStrategy::Silhouette => facing.abs() / 4.0,
+                    _ if facing > 0.0 => facing / 2.0,
+                    _ => 0.0,
+                };
+            }
+        }
+        scores
+    }
+}
+
+/// Returns the given position or direction of the part in the renderer's
+/// coordinates.
+pub fn to_render_space<S: Neg<Output = S>>(vector: [S; 3]) -> Vector3<S> {
+    let [x, y, z] = vector;
+    Vector3::new(x, z, -y)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn part(triangles: &[[[f32; 3]; 3]]) -> Part {
+        let mut part = Part::new();
+        let body = part.add_body("Mesh");
+        for &triangle in triangles {
+            part.add_flat_triangle(triangle, crate::part::face_normal(&triangle), body);
+        }
+        part
+    }
+
+    fn assert_direction(view: View, expected: Vector3<f32>) {
+        let expected = expected.normalize();
+        assert!(
+            (view.direction - expected).magnitude() < 1e-6,
+            "Looking along {:?} instead of {:?}",
+            view.direction,
+            expected
+        );
+    }
+
+    #[test]
+    fn near_ties_go_to_the_earlier_view() {
+        // The isometric views of the sample box only differ by rounding error.
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/res/rounded_box.stl");
+        let part = crate::loader::load(path, None, None).unwrap();
+        let bounding_box = BoundingBox::new(&part);
+        for strategy in [
+            ViewStrategy::BoundingBox,
+            ViewStrategy::Silhouette,
+            ViewStrategy::VisibleSurface,
+        ]
+        .iter()
+        {
+            let view = strategy.pick_view(&part, &bounding_box);
+            assert_direction(view, Vector3::new(1.0, 1.0, 1.0));
+        }
+    }
+
+    #[test]
+    fn visible_triangles_count_by_their_projected_area() {
+        // A square facing between the front and the right of the part, which
+        // the isometric view from the front right top sees most squarely.
+        let square = part(&[
+            [[0.0, 0.0, 0.0], [1.0, 1.0, 0.0], [0.0, 0.0, 1.0]],
+            [[1.0, 1.0, 0.0], [1.0, 1.0, 1.0], [0.0, 0.0, 1.0]],
+        ]);
+        let view = ViewStrategy::VisibleSurface.pick_view(&square, &BoundingBox::new(&square));
+        assert_direction(view, Vector3::new(1.0, 1.0, 1.0));
+    }
+}